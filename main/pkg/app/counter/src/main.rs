@@ -4,13 +4,12 @@
 use lib::*;
 extern crate lib;
 
-use spin::*;
-
 const THREAD_COUNT: usize = 8;
 static mut COUNTER: isize = 0;
 
 static SEMPH: Semaphore = Semaphore::new(0xDEADBEEF);
-static SPLOK: Mutex<SpinLock> = Mutex::new(SpinLock::new());
+static SPLOK: spin::Mutex<SpinLock> = spin::Mutex::new(SpinLock::new());
+static MUTEX: Mutex<isize> = Mutex::new(0);
 
 fn main() -> isize {
     let pid = sys_fork();
@@ -20,6 +19,7 @@ fn main() -> isize {
     } else {
         sys_wait_pid(pid);
         test_spinlock();
+        test_mutex();
     }
 
     0
@@ -81,6 +81,32 @@ fn test_spinlock() {
     println!("COUNTER result: {}", unsafe { COUNTER });
 }
 
+fn test_mutex() {
+    let mut pids = [0u16; THREAD_COUNT];
+
+    for i in 0..THREAD_COUNT {
+        let pid = sys_fork();
+        if pid == 0 {
+            for _ in 0..100 {
+                let mut counter = MUTEX.lock();
+                delay();
+                *counter += 1;
+            }
+            sys_exit(0);
+        } else {
+            pids[i] = pid; // only parent knows child's pid
+        }
+    }
+
+    let cpid = sys_get_pid();
+    for i in 0..THREAD_COUNT {
+        println!("#{} waiting for #{}...", cpid, pids[i]);
+        sys_wait_pid(pids[i]);
+    }
+
+    println!("MUTEX result: {}", *MUTEX.lock());
+}
+
 fn do_counter_inc() {
     for _ in 0..100 {
         // DONE: protect the critical section
@@ -46,7 +46,7 @@ fn philosopher(id: usize) -> ! {
     for _ in 0..0x100 {
         // Think
         println!("Philosopher #{id} (process #{pid}) is thinking");
-        delay();
+        sys_sleep(10);
 
         // Eat
         MUTEX.wait();
@@ -60,11 +60,3 @@ fn philosopher(id: usize) -> ! {
 
     sys_exit(0);
 }
-
-#[inline(never)]
-#[unsafe(no_mangle)]
-fn delay() {
-    for _ in 0..0x100 {
-        core::hint::spin_loop();
-    }
-}
@@ -37,11 +37,11 @@ fn main() -> isize {
 
         println!("Waiting for child to exit...");
 
-        let ret = sys_wait_pid(pid);
+        let ret = sys_wait_pid(pid).expect("Failed to wait for child");
 
-        println!("Child exited with status {}", ret);
+        println!("Child exited with {}", ret);
 
-        assert_eq!(ret, 64);
+        assert_eq!(ret.code(), Some(64));
 
         unsafe {
             println!("parent read value of M: {:#x}", *m_ptr);
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+use lib::*;
+extern crate lib;
+
+/// Priorities only matter with `sched=mlfq`, 0 is the highest
+const HIGH: usize = 0;
+const MEDIUM: usize = 1;
+const LOW: usize = 3;
+
+const MEDIUM_COUNT: usize = 3;
+
+static LOCK: KernelMutex = KernelMutex::new(0x1A7E);
+
+entry!(main);
+fn main() -> isize {
+    LOCK.init();
+
+    let mut pids = [0u16; MEDIUM_COUNT + 2];
+
+    // The low priority process takes the lock first, the high priority one
+    // then waits for it while the medium ones keep the cpu busy.
+    // Without priority inheritance, the low one cannot run to release the lock.
+    pids[0] = fork(low);
+    pids[1] = fork(high);
+    for i in 0..MEDIUM_COUNT {
+        pids[i + 2] = fork(medium);
+    }
+
+    for pid in pids {
+        sys_wait_pid(pid);
+    }
+
+    LOCK.free();
+
+    0
+}
+
+fn fork(f: fn()) -> u16 {
+    let pid = sys_fork();
+    if pid == 0 {
+        f();
+        sys_exit(0);
+    }
+    pid
+}
+
+fn low() {
+    nice(LOW);
+    let pid = sys_get_pid();
+
+    LOCK.lock();
+    println!("LOW    #{pid} holds the lock");
+    work(0x400);
+    println!("LOW    #{pid} releases the lock");
+    LOCK.unlock();
+}
+
+fn high() {
+    nice(HIGH);
+    let pid = sys_get_pid();
+
+    // let the low one take the lock
+    sys_sleep(10);
+
+    let start = uptime().unwrap_or_default();
+    LOCK.lock();
+    let waited = uptime().unwrap_or_default() - start;
+    println!("HIGH   #{pid} got the lock after {} ms", waited.as_millis());
+    LOCK.unlock();
+}
+
+fn medium() {
+    nice(MEDIUM);
+    let pid = sys_get_pid();
+
+    sys_sleep(20);
+    println!("MEDIUM #{pid} starts working");
+    work(0x1000);
+    println!("MEDIUM #{pid} is done");
+}
+
+/// Keep the cpu busy
+fn work(rounds: usize) {
+    for _ in 0..rounds {
+        delay();
+    }
+}
+
+#[inline(never)]
+#[unsafe(no_mangle)]
+fn delay() {
+    for _ in 0..0x1000 {
+        core::hint::spin_loop();
+    }
+}
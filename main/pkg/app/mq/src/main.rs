@@ -4,22 +4,26 @@
 use lib::*;
 extern crate lib;
 
-static MUTEX: Semaphore = Semaphore::new(0xBABEBABE);
-static EMPTY: Semaphore = Semaphore::new(0xBABFBABF);
+static QUEUE: MessageQueue = MessageQueue::new(0xBABEBABE);
 
-static mut COUNT: usize = 0;
+/// Small enough that producers have to wait for consumers
+const CAPACITY: usize = 4;
+const MSG_SIZE: usize = 64;
+const MESSAGES: usize = 10;
 
 entry!(main);
 fn main() -> isize {
-    MUTEX.init(1);
-    EMPTY.init(0);
+    if !QUEUE.init(CAPACITY, MSG_SIZE) {
+        errln!("Failed to create the message queue");
+        return 1;
+    }
 
     let mut pids = [0u16; 16];
     // Fork producers and consumers.
     for i in 0..16 {
         let pid = sys_fork();
         if pid == 0 { // Child Branch
-            if i % 2 == 0 { producer() } else { consumer() }
+            if i % 2 == 0 { producer(i / 2) } else { consumer() }
         } else { // Parent Branch
             pids[i] = pid;
         }
@@ -34,54 +38,40 @@ fn main() -> isize {
         sys_wait_pid(pid);
     }
 
-    MUTEX.free();
-    EMPTY.free();
+    QUEUE.free();
 
     0
 }
 
-fn producer() -> ! {
+fn producer(id: usize) -> ! {
     let pid = sys_get_pid();
-    for _ in 0..10 {
-        delay();
-        // Wait for other IO operations.
-        MUTEX.wait();
-        // Add a message (simulated by a number).
-        unsafe {
-            COUNT += 1;
+    for seq in 0..MESSAGES {
+        sys_sleep(50);
+        // Every producer sends with its own priority, the last message is urgent.
+        let priority = if seq + 1 == MESSAGES { MQ_PRIO_MAX - 1 } else { id };
+        let msg = format!("#{pid} message {seq}");
+        // Blocks while the queue is full.
+        if !QUEUE.send(msg.as_bytes(), priority) {
+            errln!("Process #{pid} failed to send a message");
         }
-        println!("Process #{pid} produced a message, current count: {}", unsafe { COUNT });
-        // Signal on finishing.
-        MUTEX.signal();
-        // Signal that the queue is not empty.
-        EMPTY.signal();
+        println!("Process #{pid} sent \"{msg}\" with priority {priority}");
     }
     sys_exit(0);
 }
 
 fn consumer() -> ! {
     let pid = sys_get_pid();
-    for _ in 0..10 {
-        delay();
-        // Wait if message queue is empty.
-        EMPTY.wait();
-        // Wait for other IO operations.
-        MUTEX.wait();
-        // Remove a message (simulated by a number).
-        unsafe {
-            COUNT -= 1;
+    let mut buf = [0u8; MSG_SIZE];
+    for _ in 0..MESSAGES {
+        sys_sleep(50);
+        // Blocks while the queue is empty.
+        match QUEUE.recv(&mut buf) {
+            Some(len) => println!(
+                "Process #{pid} received \"{}\"",
+                core::str::from_utf8(&buf[..len]).unwrap_or("<invalid>")
+            ),
+            None => errln!("Process #{pid} failed to receive a message"),
         }
-        println!("Process #{pid} consumed a message, current count: {}", unsafe { COUNT });
-        // Signal on finishing.
-        MUTEX.signal();
     }
     sys_exit(0);
 }
-
-#[inline(never)]
-#[unsafe(no_mangle)]
-fn delay() {
-    for _ in 0..0x100 {
-        core::hint::spin_loop();
-    }
-}
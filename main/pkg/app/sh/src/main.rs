@@ -7,6 +7,9 @@ extern crate lib;
 fn main() -> isize {
     let mut current_dir = String::from("/APP");
 
+    // Ctrl-C is meant for the program running in the foreground
+    signal(signal::SIGINT, SignalHandler::Ignore);
+
     loop {
         print!("> ");
 
@@ -17,6 +20,11 @@ fn main() -> isize {
             continue;
         }
 
+        if input.contains('|') {
+            run_pipeline(&input);
+            continue;
+        }
+
         match args[0] {
             "exit" | "\x04" => {
                 break;
@@ -29,17 +37,27 @@ fn main() -> isize {
 
                 let name = args[1];
                 let pid = sys_spawn(name);
-                let _ = sys_wait_pid(pid);
+                if let Some(status) = sys_wait_pid(pid)
+                    && status.signal().is_some()
+                {
+                    println!("{} {}", name, status);
+                }
             },
             "help" => {
                 println!("Available commands:");
                 println!("  exec <app>        Execute an application");
+                println!("  <app> | <app>     Connect the output of an app to the next one");
                 println!("  exit              Exit the shell");
                 println!("  help              Show this help message");
                 println!("  cat <file>        Display file contents");
                 println!("  cd <dir>          Change current directory");
+                println!("  mkfifo <path>     Create a named pipe");
                 println!("  ls [dir]          List directory contents");
                 println!("  ps                Show process information");
+                println!("  sems              Show who holds and waits for semaphores");
+                println!("  kill <pid> [sig]  Send a signal to a process");
+                println!("  date              Show the current date and time");
+                println!("  uptime            Show the time since boot");
             },
             "cat" => {
                 if args.len() < 2 {
@@ -47,18 +65,7 @@ fn main() -> isize {
                     continue;
                 }
 
-                let path = if args[1].starts_with('/') {
-                    // Absolute path
-                    String::from(args[1])
-                } else {
-                    // Relative path
-                    if current_dir.ends_with('/') {
-                        format!("{}{}", current_dir, args[1])
-                    } else {
-                        format!("{}/{}", current_dir, args[1])
-                    }
-                }
-                .to_ascii_uppercase();
+                let path = absolute_path(&current_dir, args[1]);
 
                 let fd = sys_open(path.as_str());
 
@@ -82,6 +89,17 @@ fn main() -> isize {
 
                 sys_close(fd);
             },
+            "mkfifo" => {
+                if args.len() < 2 {
+                    println!("Usage: mkfifo <path>");
+                    continue;
+                }
+
+                let path = absolute_path(&current_dir, args[1]);
+                if !sys_mkfifo(path.as_str()) {
+                    errln!("Failed to create {}", path);
+                }
+            },
             "cd" => {
                 if args.len() < 2 {
                     println!("Usage: cd <directory>");
@@ -121,6 +139,38 @@ fn main() -> isize {
                 }
             },
             "ps" => sys_stat(),
+            "sems" => sys_sem_dump(),
+            "date" => match date() {
+                Some(now) => println!("{}", now.format("%Y/%m/%d %H:%M:%S UTC")),
+                None => errln!("Failed to get the time"),
+            },
+            "uptime" => match uptime() {
+                Some(up) => println!("up {}.{:03}s", up.as_secs(), up.subsec_millis()),
+                None => errln!("Failed to get the uptime"),
+            },
+            "kill" => {
+                if args.len() < 2 {
+                    println!("Usage: kill <pid> [signal]");
+                    continue;
+                }
+
+                let Ok(pid) = args[1].parse::<u16>() else {
+                    errln!("Invalid pid");
+                    continue;
+                };
+                let sig = match args.get(2).map(|s| s.parse::<usize>()) {
+                    None => signal::SIGTERM,
+                    Some(Ok(sig)) => sig,
+                    Some(Err(_)) => {
+                        errln!("Invalid signal");
+                        continue;
+                    }
+                };
+
+                if !sys_kill(pid, sig) {
+                    errln!("Failed to send signal {} to #{}", sig, pid);
+                }
+            },
             _ => {
                 println!("Command not found: {}", args[0]);
             },
@@ -130,4 +180,80 @@ fn main() -> isize {
     0
 }
 
+fn absolute_path(current_dir: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        // Absolute path
+        String::from(path)
+    } else {
+        // Relative path
+        if current_dir.ends_with('/') {
+            format!("{}{}", current_dir, path)
+        } else {
+            format!("{}/{}", current_dir, path)
+        }
+    }
+    .to_ascii_uppercase()
+}
+
+/// Run apps connected by pipes, e.g. `exec a | b`, and wait for all of them
+fn run_pipeline(input: &str) {
+    let names: Vec<&str> = input
+        .split('|')
+        .map(|cmd| cmd.trim())
+        .map(|cmd| cmd.strip_prefix("exec ").unwrap_or(cmd).trim())
+        .collect();
+
+    if names.iter().any(|name| name.is_empty()) {
+        errln!("Invalid pipeline");
+        return;
+    }
+
+    let mut pids = Vec::new();
+    let mut stdin = 0;
+    for (i, name) in names.iter().enumerate() {
+        // the ends are only inherited as stdin and stdout of the stage,
+        // otherwise a stage keeps its own pipe open
+        let (next_stdin, stdout) = if i + 1 < names.len() {
+            match sys_pipe_with(O_CLOEXEC) {
+                Some(pipe) => pipe,
+                None => {
+                    errln!("Failed to create a pipe");
+                    break;
+                }
+            }
+        } else {
+            (0, 1)
+        };
+
+        let pid = sys_spawn_with(name, stdin, stdout);
+        if pid == 0 {
+            errln!("Failed to spawn {}", name);
+        } else {
+            pids.push((pid, *name));
+        }
+
+        // only the children may keep the pipe open,
+        // otherwise the reader never sees the end of file
+        if stdin != 0 {
+            sys_close(stdin);
+        }
+        if stdout != 1 {
+            sys_close(stdout);
+        }
+        stdin = next_stdin;
+    }
+
+    if stdin != 0 {
+        sys_close(stdin);
+    }
+
+    for (pid, name) in pids {
+        if let Some(status) = sys_wait_pid(pid)
+            && status.signal().is_some()
+        {
+            println!("{} {}", name, status);
+        }
+    }
+}
+
 entry!(main);
@@ -52,6 +52,19 @@ pub struct BootInfo {
 
     /// Kernel pages
     pub kernel_pages: KernelPages,
+
+    /// Kernel command line, whitespace separated `key=value` options
+    pub cmdline: ArrayString<256>,
+}
+
+impl BootInfo {
+    /// Get the value of an option on the kernel command line
+    pub fn cmdline_option(&self, key: &str) -> Option<&str> {
+        self.cmdline
+            .split_whitespace()
+            .filter_map(|opt| opt.split_once('='))
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
 }
 
 /// Get current page table from CR3
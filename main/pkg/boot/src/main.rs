@@ -6,7 +6,9 @@
 extern crate log;
 extern crate alloc;
 
+use arrayvec::ArrayString;
 use x86_64::registers::control::*;
+use x86_64::registers::model_specific::{Efer, EferFlags};
 use xmas_elf::ElfFile;
 use ysos_boot::*;
 use uefi::mem::memory_map::MemoryMap;
@@ -67,6 +69,11 @@ fn boot_main() -> Status {
         Cr0::update(|f| f.remove(Cr0Flags::WRITE_PROTECT));
     }
 
+    // `NO_EXECUTE` in page tables needs the feature enabled (Efer)
+    unsafe {
+        Efer::update(|f| f.insert(EferFlags::NO_EXECUTE_ENABLE));
+    }
+
     // DONE: map physical memory to specific virtual address offset
     elf::map_physical_memory(
         config.physical_memory_offset,
@@ -123,6 +130,11 @@ fn boot_main() -> Status {
 
     free_elf(elf);
 
+    let cmdline = ArrayString::from(config.cmdline).unwrap_or_else(|_| {
+        warn!("Kernel cmdline too long, ignored.");
+        ArrayString::new()
+    });
+
     // 6. Pass system table to kernel
     let ptr = uefi::table::system_table_raw().expect("Failed to get system table");
     let system_table = ptr.cast::<core::ffi::c_void>();
@@ -140,6 +152,7 @@ fn boot_main() -> Status {
         system_table,
         loaded_apps: apps,
         kernel_pages,
+        cmdline,
     };
 
     // align stack to 8 bytes
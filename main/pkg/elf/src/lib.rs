@@ -107,7 +107,9 @@ pub fn map_range(
         page_range.count()
     );
 
-    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    // heap and stack pages hold data only
+    let mut flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
 
     if user_access {
         flags |= PageTableFlags::USER_ACCESSIBLE;
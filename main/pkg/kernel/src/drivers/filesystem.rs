@@ -1,5 +1,9 @@
 use super::ata::*;
+use crate::pipe::Pipe;
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
 use chrono::DateTime;
 use storage::fat16::Fat16;
 use storage::mbr::*;
@@ -7,6 +11,9 @@ use storage::*;
 
 pub static ROOTFS: spin::Once<Mount> = spin::Once::new();
 
+/// FIFOs by their path, kept in memory since FAT16 cannot store them
+static FIFOS: spin::Mutex<BTreeMap<String, Arc<Pipe>>> = spin::Mutex::new(BTreeMap::new());
+
 pub fn get_rootfs() -> &'static Mount {
     ROOTFS.get().unwrap()
 }
@@ -32,6 +39,35 @@ pub fn init() {
     info!("Initialized Filesystem.");
 }
 
+/// Paths are case insensitive like on FAT16
+fn fifo_key(path: &str) -> String {
+    path.trim_end_matches('/').to_ascii_uppercase()
+}
+
+/// Create a FIFO, fails if the path is already taken
+pub fn mkfifo(path: &str) -> bool {
+    let key = fifo_key(path);
+    let Some((_, name)) = key.rsplit_once('/') else {
+        return false;
+    };
+
+    // also fails if the parent directory does not exist
+    if name.is_empty() || get_rootfs().exists(&key) != Ok(false) {
+        return false;
+    }
+
+    let mut fifos = FIFOS.lock();
+    if fifos.contains_key(&key) {
+        return false;
+    }
+    fifos.insert(key, Pipe::new());
+    true
+}
+
+pub fn open_fifo(path: &str) -> Option<Arc<Pipe>> {
+    FIFOS.lock().get(&fifo_key(path)).cloned()
+}
+
 pub fn ls(root_path: &str) {
     let iter = match get_rootfs().read_dir(root_path) {
         Ok(iter) => iter,
@@ -67,4 +103,13 @@ pub fn ls(root_path: &str) {
             if meta.is_dir() { "/" } else { "" }
         );
     }
+
+    let dir = fifo_key(root_path);
+    for path in FIFOS.lock().keys() {
+        if let Some((parent, name)) = path.rsplit_once('/')
+            && parent == dir
+        {
+            println!("  fifo | {:19} | {}|", "", name);
+        }
+    }
 }
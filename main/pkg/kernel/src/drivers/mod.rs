@@ -2,4 +2,7 @@ mod uart16550;
 pub mod ata;
 pub mod filesystem;
 pub mod input;
+pub mod pit;
+pub mod rtc;
 pub mod serial;
+pub mod tty;
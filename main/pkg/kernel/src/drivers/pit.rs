@@ -0,0 +1,44 @@
+//! Intel 8253/8254 Programmable Interval Timer
+//!
+//! only channel 2 is used, as a reference clock to calibrate other timers.
+//!
+//! Reference: [OSDev Wiki](https://wiki.osdev.org/Programmable_Interval_Timer)
+
+use x86_64::instructions::port::Port;
+
+/// Input clock of the PIT in Hz
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL2_DATA: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+/// Bit 0 gates channel 2, bit 1 drives the speaker, bit 5 is the output of channel 2
+const SPEAKER_CONTROL: u16 = 0x61;
+
+/// Busy wait for the given milliseconds, at most 54
+pub fn wait_ms(ms: u64) {
+    let count = PIT_FREQUENCY * ms / 1000;
+    assert!(count <= u16::MAX as u64, "PIT wait too long: {}ms", ms);
+
+    let mut control = Port::<u8>::new(SPEAKER_CONTROL);
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut data = Port::<u8>::new(CHANNEL2_DATA);
+
+    unsafe {
+        // gate channel 2 off and the speaker off
+        let value = control.read() & !0b11;
+        control.write(value);
+
+        // channel 2, lobyte/hibyte, mode 0 (interrupt on terminal count)
+        command.write(0b1011_0000);
+        data.write(count as u8);
+        data.write((count >> 8) as u8);
+
+        // start counting, the output goes high once the count reaches 0
+        control.write(value | 0b1);
+        while control.read() & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+
+        control.write(value);
+    }
+}
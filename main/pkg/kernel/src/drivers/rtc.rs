@@ -0,0 +1,175 @@
+//! CMOS Real-Time Clock
+//!
+//! the date and time are read once at boot, the kernel keeps the
+//! wall-clock time as an offset to the monotonic clock afterwards.
+//!
+//! Reference: [OSDev Wiki](https://wiki.osdev.org/CMOS)
+
+use core::sync::atomic::{AtomicI64, Ordering};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use syscall_def::time::TimeSpec;
+use x86_64::instructions::port::Port;
+
+use crate::interrupt::clock;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Set on the address port to keep NMIs disabled while accessing the CMOS
+const NMI_DISABLE: u8 = 0x80;
+
+const REG_SECOND: u8 = 0x00;
+const REG_MINUTE: u8 = 0x02;
+const REG_HOUR: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_CENTURY: u8 = 0x32;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+/// Status A: an update is in progress, the registers may be inconsistent
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+/// Status B: values are binary instead of BCD
+const BINARY_MODE: u8 = 0x04;
+/// Status B: hours are in 24 hour format
+const HOUR_24: u8 = 0x02;
+/// The hour register sets this bit for PM in 12 hour format
+const HOUR_PM: u8 = 0x80;
+
+/// Wall-clock time minus monotonic time, in nanoseconds
+static REALTIME_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RtcTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn read_register(reg: u8) -> u8 {
+    let mut address = Port::<u8>::new(CMOS_ADDRESS);
+    let mut data = Port::<u8>::new(CMOS_DATA);
+    unsafe {
+        address.write(NMI_DISABLE | reg);
+        data.read()
+    }
+}
+
+fn read_raw() -> RtcTime {
+    while read_register(REG_STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+
+    RtcTime {
+        second: read_register(REG_SECOND),
+        minute: read_register(REG_MINUTE),
+        hour: read_register(REG_HOUR),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: read_register(REG_CENTURY),
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+/// Read the date and time from the CMOS
+pub fn read_time() -> Option<DateTime<Utc>> {
+    // read until two reads agree, so an update in between is not missed
+    let mut time = read_raw();
+    loop {
+        let again = read_raw();
+        if again == time {
+            break;
+        }
+        time = again;
+    }
+
+    let status = read_register(REG_STATUS_B);
+    let pm = time.hour & HOUR_PM != 0;
+    time.hour &= !HOUR_PM;
+
+    if status & BINARY_MODE == 0 {
+        time.second = from_bcd(time.second);
+        time.minute = from_bcd(time.minute);
+        time.hour = from_bcd(time.hour);
+        time.day = from_bcd(time.day);
+        time.month = from_bcd(time.month);
+        time.year = from_bcd(time.year);
+        time.century = from_bcd(time.century);
+    }
+
+    if status & HOUR_24 == 0 {
+        // 12am is 0 o'clock, 12pm is 12 o'clock
+        time.hour = (time.hour % 12) + if pm { 12 } else { 0 };
+    }
+
+    // the century register is not always there
+    let century = if (19..=30).contains(&time.century) {
+        time.century as i32
+    } else {
+        20
+    };
+
+    let datetime = NaiveDate::from_ymd_opt(
+        century * 100 + time.year as i32,
+        time.month as u32,
+        time.day as u32,
+    )?
+    .and_hms_opt(time.hour as u32, time.minute as u32, time.second as u32)?;
+
+    Some(datetime.and_utc())
+}
+
+pub fn init() {
+    let time = read_time().filter(|time| {
+        set_realtime(TimeSpec {
+            sec: time.timestamp(),
+            nsec: 0,
+        })
+    });
+
+    match time {
+        Some(time) => {
+            info!("RTC Initialized, time: {}", time.format("%Y/%m/%d %H:%M:%S"));
+        }
+        None => warn!("Failed to read time from RTC."),
+    }
+}
+
+/// Time since boot
+pub fn monotonic() -> TimeSpec {
+    TimeSpec::from_nanos(clock::monotonic_ns() as i64)
+}
+
+/// Time since the Unix epoch
+pub fn realtime() -> TimeSpec {
+    let offset = REALTIME_OFFSET.load(Ordering::Relaxed);
+    let now = (clock::monotonic_ns() as i64).saturating_add(offset);
+    TimeSpec::from_nanos(now)
+}
+
+/// Set the wall-clock time, the monotonic clock is not affected
+///
+/// fails if the time is out of range
+pub fn set_realtime(time: TimeSpec) -> bool {
+    if !time.is_valid() {
+        return false;
+    }
+    let Some(offset) = time
+        .checked_as_nanos()
+        .and_then(|nanos| nanos.checked_sub(clock::monotonic_ns() as i64))
+    else {
+        return false;
+    };
+
+    REALTIME_OFFSET.store(offset, Ordering::Relaxed);
+    true
+}
@@ -0,0 +1,270 @@
+//! Terminal line discipline
+//!
+//! bytes received by the UART are queued in the input buffer, the tty
+//! drains them, handles line editing, echo and the interrupt character,
+//! and keeps the input that is ready to be read by `Resource::Console`.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+use syscall_def::tty::*;
+
+use super::input::try_pop_key;
+
+/// Bytes kept for readers before input is dropped
+const MAX_INPUT: usize = 4096;
+/// Longest line in canonical mode, the newline always fits
+const MAX_LINE: usize = 1024;
+
+const ESC: u8 = 0x1B;
+/// Erased like `VERASE`, terminals send either of them for backspace
+const BS: u8 = 0x08;
+
+static TTY: Mutex<Tty> = Mutex::new(Tty::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// got ESC
+    Start,
+    /// got ESC [, until the final byte
+    Csi,
+}
+
+/// What has to be done once the tty is unlocked
+#[derive(Default)]
+struct Events {
+    readable: bool,
+    interrupt: bool,
+}
+
+#[derive(Debug)]
+struct Tty {
+    termios: Option<Termios>,
+    /// line being edited in canonical mode
+    line: Vec<u8>,
+    /// input ready to be read, one chunk per line in canonical mode,
+    /// an empty chunk is an end of file
+    ready: VecDeque<Vec<u8>>,
+    buffered: usize,
+    escape: Escape,
+}
+
+impl Tty {
+    const fn new() -> Self {
+        Self {
+            termios: None,
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            buffered: 0,
+            escape: Escape::None,
+        }
+    }
+
+    fn termios(&self) -> Termios {
+        self.termios.unwrap_or_default()
+    }
+
+    fn echo(&self, s: &str) {
+        if self.termios().lflag & ECHO != 0 {
+            print!("{}", s);
+        }
+    }
+
+    fn push_ready(&mut self, chunk: Vec<u8>) -> bool {
+        if self.buffered + chunk.len() > MAX_INPUT {
+            warn!("TTY input buffer is full. Dropping {} bytes", chunk.len());
+            return false;
+        }
+        self.buffered += chunk.len();
+        self.ready.push_back(chunk);
+        true
+    }
+
+    /// Make the line being edited available to readers
+    fn flush_line(&mut self) -> bool {
+        let line = core::mem::take(&mut self.line);
+        self.push_ready(line)
+    }
+
+    fn erase(&mut self) -> bool {
+        if self.line.is_empty() {
+            return false;
+        }
+        // drop a whole utf-8 character
+        while let Some(byte) = self.line.pop() {
+            if byte & 0xC0 != 0x80 {
+                break;
+            }
+        }
+        self.echo("\x08 \x08");
+        true
+    }
+
+    fn receive(&mut self, mut byte: u8, events: &mut Events) {
+        let termios = self.termios();
+
+        if termios.iflag & ICRNL != 0 && byte == b'\r' {
+            byte = b'\n';
+        }
+
+        if termios.lflag & ISIG != 0 && byte == termios.cc[VINTR] {
+            self.echo("^C\n");
+            self.line.clear();
+            self.ready.clear();
+            self.buffered = 0;
+            self.escape = Escape::None;
+            events.interrupt = true;
+            return;
+        }
+
+        if !termios.is_canonical() {
+            if self.push_ready(alloc::vec![byte]) {
+                if byte.is_ascii() {
+                    self.echo(char::from(byte).encode_utf8(&mut [0; 4]));
+                }
+                events.readable = true;
+            }
+            return;
+        }
+
+        // escape sequences like arrow keys cannot be edited, drop them
+        match self.escape {
+            Escape::Start => {
+                self.escape = if byte == b'[' { Escape::Csi } else { Escape::None };
+                return;
+            }
+            Escape::Csi => {
+                if (0x40..=0x7E).contains(&byte) {
+                    self.escape = Escape::None;
+                }
+                return;
+            }
+            Escape::None => {}
+        }
+
+        match byte {
+            ESC => self.escape = Escape::Start,
+            b'\n' => {
+                self.line.push(b'\n');
+                self.echo("\n");
+                events.readable |= self.flush_line();
+            }
+            BS => {
+                self.erase();
+            }
+            byte if byte == termios.cc[VERASE] => {
+                self.erase();
+            }
+            byte if byte == termios.cc[VKILL] => while self.erase() {},
+            byte if byte == termios.cc[VEOF] => {
+                // an empty line is read as the end of file
+                events.readable |= self.flush_line();
+            }
+            byte => {
+                if self.line.len() >= MAX_LINE - 1 {
+                    return;
+                }
+                self.line.push(byte);
+                // echo a utf-8 character once it is complete
+                if let Some(ch) = last_char(&self.line) {
+                    self.echo(ch.encode_utf8(&mut [0; 4]));
+                }
+            }
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let canonical = self.termios().is_canonical();
+        let mut count = 0;
+
+        while count < buf.len() {
+            let Some(mut chunk) = self.ready.pop_front() else {
+                break;
+            };
+
+            if chunk.is_empty() {
+                // the end of file is only returned on its own
+                if count > 0 {
+                    self.ready.push_front(chunk);
+                }
+                break;
+            }
+
+            let len = chunk.len().min(buf.len() - count);
+            buf[count..count + len].copy_from_slice(&chunk[..len]);
+            count += len;
+            self.buffered -= len;
+
+            if len < chunk.len() {
+                chunk.drain(..len);
+                self.ready.push_front(chunk);
+            }
+
+            // at most one line per read
+            if canonical {
+                break;
+            }
+        }
+
+        count
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        // a partial line is kept when leaving canonical mode
+        if self.termios().is_canonical() && !termios.is_canonical() && !self.line.is_empty() {
+            self.flush_line();
+        }
+        self.escape = Escape::None;
+        self.termios = Some(termios);
+    }
+}
+
+/// The last character of the buffer, if it is a complete utf-8 sequence
+fn last_char(buf: &[u8]) -> Option<char> {
+    let start = buf.iter().rposition(|&b| b & 0xC0 != 0x80)?;
+    core::str::from_utf8(&buf[start..]).ok()?.chars().next()
+}
+
+/// Run the bytes in the input buffer through the line discipline
+pub fn process_input() {
+    let mut events = Events::default();
+
+    {
+        let mut tty = TTY.lock();
+        while let Some(byte) = try_pop_key() {
+            tty.receive(byte, &mut events);
+        }
+    }
+
+    if events.interrupt {
+        crate::proc::interrupt_foreground();
+    }
+    if events.readable {
+        crate::proc::wake_channel(crate::CONSOLE_CHANNEL);
+    }
+}
+
+/// Whether a read would return without waiting
+pub fn can_read() -> bool {
+    !TTY.lock().ready.is_empty()
+}
+
+/// Read the input that is ready, 0 at the end of file
+pub fn read(buf: &mut [u8]) -> usize {
+    TTY.lock().read(buf)
+}
+
+/// Handle a terminal `Ioctl` request on a copy of the user's `Termios`
+pub fn ioctl(request: usize, termios: &mut Termios) -> Option<usize> {
+    match request {
+        TCGETS => *termios = TTY.lock().termios(),
+        TCSETS => {
+            TTY.lock().set_termios(*termios);
+            // leftovers may be readable in the new mode
+            crate::proc::wake_channel(crate::CONSOLE_CHANNEL);
+        }
+        _ => return None,
+    }
+    Some(0)
+}
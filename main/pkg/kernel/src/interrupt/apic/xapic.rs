@@ -4,6 +4,7 @@ use core::fmt::{Debug, Error, Formatter};
 use core::ptr::{read_volatile, write_volatile};
 use x86::cpuid::CpuId;
 
+use crate::interrupt::clock;
 use crate::interrupt::consts::{Interrupts, Irq};
 
 // Default physical address of xAPIC
@@ -20,6 +21,7 @@ impl LapicRegister {
     const LVT_LINT1: u32 = 0x360;
     const LVT_ERROR: u32 = 0x370;
     const ICR: u32 = 0x380;
+    const CCR: u32 = 0x390;
     const DCR: u32 = 0x3E0;
 }
 // Local APIC BitFlags
@@ -61,6 +63,28 @@ impl XApic {
     }
 }
 
+impl XApic {
+    /// Measure the timer against the PIT, returns its counts per second
+    unsafe fn calibrate_timer(&mut self) -> u64 {
+        const CALIBRATE_MS: u64 = 10;
+
+        unsafe {
+            // one-shot and masked, just let it count down
+            self.write(LapicRegister::LVT_TIMER, LvtFlags::MASKED.bits());
+            self.write(LapicRegister::ICR, u32::MAX);
+
+            crate::drivers::pit::wait_ms(CALIBRATE_MS);
+
+            let elapsed = u32::MAX - self.read(LapicRegister::CCR);
+            self.write(LapicRegister::ICR, 0);
+
+            let per_sec = elapsed as u64 * (1000 / CALIBRATE_MS);
+            info!("LAPIC timer: {} counts per second", per_sec);
+            per_sec
+        }
+    }
+}
+
 impl LocalApic for XApic {
     /// If this type APIC is supported
     fn support() -> bool {
@@ -78,10 +102,16 @@ impl LocalApic for XApic {
             spiv.insert(SpuriousFlags::VECTOR_IRQ);
             self.write(LapicRegister::SVR, spiv.bits());
 
-            // Set Initial Count.
-            self.write(LapicRegister::ICR, 0x00020000);
             // Set Timer Divide.
             self.write(LapicRegister::DCR, 0x0000000B);
+            // Set Initial Count, so that the timer fires every tick.
+            let count = self.calibrate_timer() / clock::frequency();
+            // a count of zero stops the timer
+            let clamped = count.clamp(1, u32::MAX as u64);
+            if clamped != count {
+                warn!("APIC timer count {} out of range, clamped to {}", count, clamped);
+            }
+            self.write(LapicRegister::ICR, clamped as u32);
             // DONE: The timer repeatedly counts down at bus frequency
             let mut timer = LvtFlags::from_bits_truncate(self.read(LapicRegister::LVT_TIMER));
             timer.remove(LvtFlags::MASKED);
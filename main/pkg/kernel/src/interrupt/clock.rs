@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use super::consts;
 
 use crate::memory::gdt;
@@ -16,8 +18,82 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     }
 }
 
+/// Timer interrupts per second unless `timer_hz` is given on the cmdline
+pub const DEFAULT_TIMER_HZ: u64 = 1000;
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+static TIMER_HZ: AtomicU64 = AtomicU64::new(DEFAULT_TIMER_HZ);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// TSC frequency in Hz, 0 if not calibrated
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static BOOT_TSC: AtomicU64 = AtomicU64::new(0);
+
+/// Set the timer frequency and calibrate the TSC against the PIT
+///
+/// must be called before the LAPIC timer is programmed
+pub fn init(timer_hz: Option<&str>) {
+    const CALIBRATE_MS: u64 = 10;
+
+    if let Some(hz) = timer_hz {
+        match hz.parse::<u64>() {
+            Ok(hz @ 10..=10000) => TIMER_HZ.store(hz, Ordering::Relaxed),
+            _ => warn!("Invalid timer_hz '{}', using {}Hz.", hz, DEFAULT_TIMER_HZ),
+        }
+    }
+
+    let start = rdtsc();
+    crate::drivers::pit::wait_ms(CALIBRATE_MS);
+    let tsc_hz = (rdtsc() - start) * (1000 / CALIBRATE_MS);
+
+    BOOT_TSC.store(start, Ordering::Relaxed);
+    TSC_HZ.store(tsc_hz, Ordering::Relaxed);
+
+    info!("Timer: {}Hz, TSC: {}MHz", frequency(), tsc_hz / 1_000_000);
+}
+
+#[inline]
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Timer interrupts per second
+#[inline]
+pub fn frequency() -> u64 {
+    TIMER_HZ.load(Ordering::Relaxed)
+}
+
+/// Nanoseconds between two timer interrupts
+#[inline]
+pub fn tick_ns() -> u64 {
+    NANOS_PER_SEC / frequency()
+}
+
+/// Timer interrupts since boot
+#[inline]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since boot in nanoseconds
+///
+/// read from the TSC, falls back to counting ticks if it is not calibrated
+pub fn monotonic_ns() -> u64 {
+    let tsc_hz = TSC_HZ.load(Ordering::Relaxed);
+    if tsc_hz == 0 {
+        return ticks() * tick_ns();
+    }
+
+    let cycles = rdtsc() - BOOT_TSC.load(Ordering::Relaxed);
+    (cycles as u128 * NANOS_PER_SEC as u128 / tsc_hz as u128) as u64
+}
+
 pub extern "C" fn clock(mut context: ProcessContext) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    crate::proc::wake_sleepers(monotonic_ns());
     crate::proc::switch(&mut context);
+    crate::proc::handle_signals(&mut context);
     super::ack();
 }
 as_handler!(clock);
@@ -1,7 +1,33 @@
 use crate::memory::*;
+use crate::proc::ExitStatus;
+use crate::proc::signal::*;
+use x86_64::PrivilegeLevel;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
+fn is_user_fault(stack_frame: &InterruptStackFrame) -> bool {
+    stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3
+}
+
+/// Kill the current process for a fault it caused in user mode
+///
+/// the faulting instruction must not be resumed, so wait here until
+/// the timer interrupt switches to another process
+fn kill_faulting_process(name: &str, signal: usize, stack_frame: &InterruptStackFrame) -> ! {
+    warn!(
+        "EXCEPTION: {} in user mode at {:#x}, killing process #{}",
+        name,
+        stack_frame.instruction_pointer,
+        crate::proc::current_pid()
+    );
+
+    crate::proc::kill_current(ExitStatus::signaled(signal));
+
+    loop {
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
 pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     unsafe {
         idt.divide_error.set_handler_fn(divide_error_handler);
@@ -38,10 +64,16 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
 }
 
 pub extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("DIVIDE ERROR", SIGFPE, &stack_frame);
+    }
     panic!("EXCEPTION: DIVIDE ERROR\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("DEBUG", SIGTRAP, &stack_frame);
+    }
     panic!("EXCEPTION: DEBUG\n\n{:#?}", stack_frame);
 }
 
@@ -50,22 +82,37 @@ pub extern "x86-interrupt" fn nmi_handler(stack_frame: InterruptStackFrame) {
 }
 
 pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("BREAKPOINT", SIGTRAP, &stack_frame);
+    }
     panic!("EXCEPTION: BREAKPOINT\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("OVERFLOW", SIGSEGV, &stack_frame);
+    }
     panic!("EXCEPTION: OVERFLOW\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("BOUND RANGE EXCEEDED", SIGSEGV, &stack_frame);
+    }
     panic!("EXCEPTION: BOUND RANGE EXCEEDED\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("INVALID OPCODE", SIGILL, &stack_frame);
+    }
     panic!("EXCEPTION: INVALID OPCODE\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("DEVICE NOT AVAILABLE", SIGFPE, &stack_frame);
+    }
     panic!("EXCEPTION: DEVICE NOT AVAILABLE\n\n{:#?}", stack_frame);
 }
 
@@ -93,6 +140,9 @@ pub extern "x86-interrupt" fn segment_not_present_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("SEGMENT NOT PRESENT", SIGBUS, &stack_frame);
+    }
     panic!(
         "EXCEPTION: SEGMENT NOT PRESENT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
         error_code, stack_frame
@@ -103,6 +153,9 @@ pub extern "x86-interrupt" fn stack_segment_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("STACK SEGMENT FAULT", SIGBUS, &stack_frame);
+    }
     panic!(
         "EXCEPTION: STACK SEGMENT FAULT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
         error_code, stack_frame
@@ -113,6 +166,9 @@ pub extern "x86-interrupt" fn general_protection_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("GENERAL PROTECTION FAULT", SIGSEGV, &stack_frame);
+    }
     panic!(
         "EXCEPTION: GENERAL PROTECTION FAULT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
         error_code, stack_frame
@@ -123,6 +179,9 @@ pub extern "x86-interrupt" fn alignment_check_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("ALIGNMENT CHECK", SIGBUS, &stack_frame);
+    }
     panic!(
         "EXCEPTION: ALIGNMENT CHECK, ERROR_CODE: 0x{:016x}\n\n{:#?}",
         error_code, stack_frame
@@ -134,16 +193,31 @@ pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackF
 }
 
 pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    if is_user_fault(&stack_frame) {
+        kill_faulting_process("SIMD FLOATING POINT", SIGFPE, &stack_frame);
+    }
     panic!("EXCEPTION: SIMD FLOATING POINT\n\n{:#?}", stack_frame);
 }
 
 pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     err_code: PageFaultErrorCode,
 ) {
     let addr = Cr2::read().unwrap();
 
     if !crate::proc::handle_page_fault(addr, err_code) {
+        if is_user_fault(&stack_frame) {
+            warn!("Page fault at {:#x} in user mode, error code: {:?}", addr, err_code);
+            kill_faulting_process("PAGE FAULT", SIGSEGV, &stack_frame);
+        }
+
+        // the copy of user memory gives up instead
+        if let Some(fixup) = crate::memory::fault_fixup(stack_frame.instruction_pointer) {
+            warn!("Page fault at {:#x} while copying user memory", addr);
+            unsafe { stack_frame.as_mut().update(|frame| frame.instruction_pointer = fixup) };
+            return;
+        }
+
         warn!(
             "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}\n{:#?}",
             err_code, addr, stack_frame
@@ -6,6 +6,7 @@ mod exceptions;
 mod syscall;
 
 use apic::*;
+use boot::BootInfo;
 use x86_64::structures::idt::InterruptDescriptorTable;
 use crate::memory::physical_to_virtual;
 use crate::interrupt::consts::*;
@@ -24,9 +25,12 @@ lazy_static! {
 }
 
 /// init interrupts system
-pub fn init() {
+pub fn init(boot_info: &'static BootInfo) {
     IDT.load();
 
+    // the timer frequency is needed to program the LAPIC timer
+    clock::init(boot_info.cmdline_option("timer_hz"));
+
     // DONE: check and init APIC
     unsafe {
         let mut lapic = XApic::new(physical_to_virtual(LAPIC_ADDR));
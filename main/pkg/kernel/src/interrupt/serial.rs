@@ -17,7 +17,9 @@ pub extern "x86-interrupt" fn serial_handler(_st: InterruptStackFrame) {
 /// Should be called on every interrupt
 fn receive() {
     // DONE: receive character from uart 16550, put it into INPUT_BUFFER
-    if let Some(byte) = get_serial_for_sure().receive() {
+    let byte = get_serial_for_sure().receive();
+    if let Some(byte) = byte {
         push_key(byte);
-    };
+        crate::tty::process_input();
+    }
 }
@@ -26,6 +26,7 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
 pub extern "C" fn syscall(mut context: ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         super::syscall::dispatcher(&mut context);
+        handle_signals(&mut context);
     });
 }
 
@@ -51,31 +52,89 @@ pub fn dispatcher(context: &mut ProcessContext) {
 
     match args.syscall {
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
-        Syscall::Read => context.set_rax(sys_read(&args)),
+        Syscall::Read => sys_read(&args, context),
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
-        Syscall::Write => context.set_rax(sys_write(&args)),
-        // path: &str (arg0 as *const u8, arg1 as len) -> fd: u8
-        Syscall::Open => context.set_rax(sys_open(&args)),
+        Syscall::Write => sys_write(&args, context),
+        // path: &str (arg0 as *const u8, arg1 as len), flags: arg2 -> fd: u8
+        Syscall::Open => sys_open(&args, context),
         // fd: arg0 as u8 -> ret: 0/1
         Syscall::Close => context.set_rax(sys_close(&args)),
-
+        // flags: arg0 -> read fd | write fd << 8
+        Syscall::Pipe => context.set_rax(sys_pipe(&args)),
+        // path: &str (arg0 as *const u8, arg1 as len) -> 0 or -1
+        Syscall::Mkfifo => context.set_rax(sys_mkfifo(&args)),
+        // fd: arg0 as u8 -> new fd or -1
+        Syscall::Dup => context.set_rax(sys_dup(&args)),
+        // fd: arg0 as u8, new fd: arg1 as u8 -> new fd or -1
+        Syscall::Dup2 => context.set_rax(sys_dup2(&args)),
+
+        // signal: arg0, handler: arg1, restorer: arg2 -> old handler or -1
+        Syscall::Sigaction => context.set_rax(sys_sigaction(&args)),
+        // how: arg0, set: arg1 -> old mask or -1
+        Syscall::Sigprocmask => context.set_rax(sys_sigprocmask(&args)),
+        // None, the signal frame is on top of the user stack
+        Syscall::Sigreturn => sigreturn(context),
+        // fd: arg0 as u8, request: arg1, arg: arg2 -> ret or -1
+        Syscall::Ioctl => context.set_rax(sys_ioctl(&args)),
+
+        // addr or 0: arg0, len: arg1, prot | flags << 8: arg2 -> addr or -1
+        Syscall::Mmap => context.set_rax(sys_mmap(&args)),
+        // addr: arg0, len: arg1, prot: arg2 -> 0 or -1
+        Syscall::Mprotect => context.set_rax(sys_mprotect(&args)),
+        // addr: arg0, len: arg1 -> 0 or -1
+        Syscall::Munmap => context.set_rax(sys_munmap(&args)),
         // brk
         Syscall::Brk => context.set_rax(sys_brk(&args)),
 
+        // None -> 0
+        Syscall::Yield => yield_now(context),
+        // ms: arg0 as u64 -> 0
+        Syscall::Sleep => sys_sleep(&args, context),
+
         // None -> pid: u16
         Syscall::GetPid => context.set_rax(sys_get_pid() as usize),
 
         // None -> pid: u16 or 0 or -1
         Syscall::Fork => sys_fork(context),
-        // path: &str (ptr: arg0 as *const u8, len: arg1) -> pid: u16
+        // path: &str (ptr: arg0 as *const u8, len: arg1), stdin | stdout << 8: arg2 -> pid: u16
         Syscall::Spawn => context.set_rax(spawn_process(&args)),
         // ret: arg0 as isize
         Syscall::Exit => exit_process(&args, context),
-        // pid: arg0 as u16 -> status: isize
+        // pid: arg0 as isize, options: arg1, status: arg2 as *mut isize -> pid: u16 or 0 or -1
         Syscall::WaitPid => sys_wait_pid(&args, context),
+        // pid: arg0 as u16, signal: arg1 -> 0 or -1
+        Syscall::Kill => context.set_rax(sys_kill(&args)),
 
-        // op: u8, key: u32, val: usize -> ret: any
+        // op: u8, key: u32, val: usize -> 0, 1 if it does not exist,
+        // 2 if a try or timed wait fails, 3 on deadlock, the value for get-value
         Syscall::Sem => sys_sem(&args, context),
+        // op: u8, key: u32 -> 0, 1 if it does not exist or is not owned, 3 if already owned
+        Syscall::Mutex => sys_mutex(&args, context),
+
+        // op: arg0, create: (key: arg1, size: arg2) -> 0 or -1,
+        // attach: (key: arg1, addr or 0: arg2) -> addr or -1, detach: (addr: arg1) -> 0 or -1
+        Syscall::Shm => context.set_rax(sys_shm(&args)),
+
+        // pid: arg0 as u16 (0 for self), priority: arg1 -> old priority or -1
+        Syscall::SetPriority => context.set_rax(sys_set_priority(&args)),
+
+        // addr: arg0 as *const u32, op: arg1,
+        // wait: (expected: arg2) -> 0 or -1, wake: (count: arg2) -> woken or -1
+        Syscall::Futex => sys_futex(&args, context),
+
+        // clock: arg0, time: arg1 as *const TimeSpec -> 0 or -1
+        Syscall::SetTime => context.set_rax(sys_set_time(&args)),
+        // clock: arg0, time: arg1 as *mut TimeSpec -> 0 or -1
+        Syscall::ClockGetTime => context.set_rax(sys_clock_gettime(&args)),
+
+        // key: arg0 as u32, capacity: arg1, message size: arg2 -> 0 or -1
+        Syscall::MqOpen => context.set_rax(sys_mq_open(&args)),
+        // key: arg0 as u32 -> 0 or -1
+        Syscall::MqUnlink => context.set_rax(sys_mq_unlink(&args)),
+        // key | priority << 32: arg0, buf: &[u8] (ptr: arg1, len: arg2) -> 0 or -1
+        Syscall::MqSend => sys_mq_send(&args, context),
+        // key: arg0 as u32, buf: &mut [u8] (ptr: arg1, len: arg2) -> len or -1
+        Syscall::MqRecv => sys_mq_recv(&args, context),
 
         // None
         Syscall::ListDir => list_dir(&args),
@@ -86,9 +145,9 @@ pub fn dispatcher(context: &mut ProcessContext) {
         // NOTE: following syscall examples are implemented
         // ----------------------------------------------------
 
-        // layout: arg0 as *const Layout -> ptr: *mut u8
+        // size: arg0, align: arg1 -> ptr: *mut u8 or null
         Syscall::Allocate => context.set_rax(sys_allocate(&args)),
-        // ptr: arg0 as *mut u8
+        // ptr: arg0 as *mut u8, size: arg1, align: arg2
         Syscall::Deallocate => sys_deallocate(&args),
         // Unknown
         Syscall::Unknown => warn!("Unhandled syscall: {:x?}", context.regs.rax),
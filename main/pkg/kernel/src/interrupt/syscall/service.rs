@@ -1,3 +1,5 @@
+use alloc::string::String;
+use alloc::vec;
 use core::alloc::Layout;
 use x86_64::VirtAddr;
 
@@ -5,22 +7,30 @@ use crate::proc::*;
 use crate::memory::*;
 
 use super::SyscallArgs;
+use syscall_def::futex::*;
+use syscall_def::mman::*;
+use syscall_def::mq::MQ_MAX_MSG_SIZE;
+use syscall_def::time::*;
+use syscall_def::tty::*;
+
+/// Longest path or app name passed in
+const PATH_MAX: usize = 0x100;
+
+/// Most bytes moved by a read or a write, longer ones are cut short
+const IO_MAX: usize = 0x10000;
 
 pub fn spawn_process(args: &SyscallArgs) -> usize {
     // DONE: get app name by args
-    //       - core::str::from_utf8_unchecked
-    //       - core::slice::from_raw_parts
     // DONE: spawn the process by name
     // DONE: handle spawn error, return 0 if failed
     // DONE: return pid as usize
-    let name = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
+    let Some(name) = read_path(args.arg0, args.arg1) else {
+        return 0;
     };
 
-    let pid = crate::proc::spawn(name);
+    // stdin in the low byte, stdout in the next one
+    let stdio = (args.arg2 as u8, (args.arg2 >> 8) as u8);
+    let pid = crate::proc::spawn(&name, stdio);
 
     if pid.is_none() {
         warn!("spawn_process: failed to spawn process: {}", name);
@@ -30,29 +40,42 @@ pub fn spawn_process(args: &SyscallArgs) -> usize {
     pid.unwrap().0 as usize 
 }
 
-pub fn sys_write(args: &SyscallArgs) -> usize {
+pub fn sys_write(args: &SyscallArgs, context: &mut ProcessContext) {
     // DONE: get buffer and fd by args
-    //      - core::slice::from_raw_parts
     // DONE: call proc::write -> isize
     // DONE: return the result as usize
-    let buf = match as_user_slice(args.arg1, args.arg2) {
+    let buf = match UserSlice::new(args.arg1, args.arg2).truncate(IO_MAX).read() {
         Some(buf) => buf,
-        None => return usize::MAX,
+        None => return context.set_rax(usize::MAX),
     };
 
     let fd = args.arg0 as u8;
-    write(fd, buf) as usize
+    write(fd, &buf, context);
+}
+
+/// Returns the read end in the low byte, the write end in the next one
+pub fn sys_pipe(args: &SyscallArgs) -> usize {
+    match pipe(args.arg0) {
+        Some((read, write)) => read as usize | (write as usize) << 8,
+        None => usize::MAX,
+    }
 }
 
-pub fn sys_read(args: &SyscallArgs) -> usize {
+pub fn sys_read(args: &SyscallArgs, context: &mut ProcessContext) {
     // DONE: just like sys_write
-    let buf = match as_user_slice_mut(args.arg1, args.arg2) {
-        Some(buf) => buf,
-        None => return usize::MAX,
-    };
+    let user_buf = UserSlice::new(args.arg1, args.arg2).truncate(IO_MAX);
+    if !user_buf.is_writable() {
+        return context.set_rax(usize::MAX);
+    }
 
     let fd = args.arg0 as u8;
-    read(fd, buf) as usize
+    let mut buf = vec![0; user_buf.len()];
+    if let Some(len) = read(fd, &mut buf, context)
+        && len > 0
+        && !user_buf.write(&buf[..len as usize])
+    {
+        context.set_rax(usize::MAX);
+    }
 }
 
 pub fn sys_get_pid() -> u16 {
@@ -60,9 +83,113 @@ pub fn sys_get_pid() -> u16 {
     current_pid().0
 }
 
+pub fn sys_sleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    sleep(args.arg0 as u64, context);
+}
+
+pub fn sys_clock_gettime(args: &SyscallArgs) -> usize {
+    let time = match args.arg0 {
+        CLOCK_REALTIME => crate::rtc::realtime(),
+        CLOCK_MONOTONIC => crate::rtc::monotonic(),
+        _ => return usize::MAX,
+    };
+
+    if UserPtr::<TimeSpec>::new(args.arg1).write(time) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_set_time(args: &SyscallArgs) -> usize {
+    if args.arg0 != CLOCK_REALTIME {
+        return usize::MAX;
+    }
+
+    let Some(time) = UserPtr::<TimeSpec>::new(args.arg1).read() else {
+        return usize::MAX;
+    };
+
+    if !crate::rtc::set_realtime(time) {
+        return usize::MAX;
+    }
+    0
+}
+
 pub fn sys_wait_pid(args: &SyscallArgs, context: &mut ProcessContext) {
-    let pid = ProcessId(args.arg0 as u16);
-    wait_pid(pid, context);
+    use syscall_def::wait::*;
+
+    let target = match args.arg0 as isize {
+        WAIT_ANY => None,
+        pid if (1..=u16::MAX as isize).contains(&pid) => Some(ProcessId(pid as u16)),
+        _ => return context.set_rax(usize::MAX),
+    };
+    let nohang = args.arg1 & WNOHANG != 0;
+
+    // validate the status pointer before a child gets collected
+    let status_ptr = match args.arg2 {
+        0 => None,
+        addr => Some(UserPtr::<isize>::new(addr)),
+    };
+    if status_ptr.is_some_and(|ptr| !ptr.is_writable()) {
+        return context.set_rax(usize::MAX);
+    }
+
+    if let Some((pid, status)) = wait_pid(target, nohang, context) {
+        if let Some(ptr) = status_ptr {
+            ptr.write(status.raw());
+        }
+        context.set_rax(pid.0 as usize);
+    }
+}
+
+/// A pid passed by the user, `None` unless it is in the pid range
+fn pid_arg(arg: usize) -> Option<ProcessId> {
+    (1..=u16::MAX as usize).contains(&arg).then_some(ProcessId(arg as u16))
+}
+
+pub fn sys_kill(args: &SyscallArgs) -> usize {
+    let signal = args.arg1;
+    if signal != 0 && !syscall_def::signal::is_valid(signal) {
+        return usize::MAX;
+    }
+    let Some(pid) = pid_arg(args.arg0) else {
+        return usize::MAX;
+    };
+
+    if kill(pid, signal) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_sigaction(args: &SyscallArgs) -> usize {
+    // the handlers run in user mode, they only have to be user addresses
+    if !is_user_range(args.arg1, 0) || !is_user_range(args.arg2, 0) {
+        return usize::MAX;
+    }
+
+    sigaction(args.arg0, args.arg1, args.arg2).unwrap_or(usize::MAX)
+}
+
+pub fn sys_sigprocmask(args: &SyscallArgs) -> usize {
+    match sigprocmask(args.arg0, args.arg1 as u64) {
+        Some(old) => old as usize,
+        None => usize::MAX,
+    }
+}
+
+pub fn sys_set_priority(args: &SyscallArgs) -> usize {
+    let pid = match args.arg0 {
+        0 => None,
+        pid => match pid_arg(pid) {
+            Some(pid) => Some(pid),
+            None => return usize::MAX,
+        },
+    };
+
+    set_priority(pid, args.arg1).unwrap_or(usize::MAX)
 }
 
 pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
@@ -70,11 +197,138 @@ pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
         0 => context.set_rax(new_sem(args.arg1 as u32, args.arg2)),
         1 => context.set_rax(remove_sem(args.arg1 as u32)),
         2 => sem_signal(args.arg1 as u32, context),
-        3 => sem_wait(args.arg1 as u32, context),
+        3 => sem_wait(args.arg1 as u32, None, context),
+        4 => context.set_rax(sem_try_wait(args.arg1 as u32)),
+        5 => sem_wait(args.arg1 as u32, Some(args.arg2 as u64), context),
+        6 => context.set_rax(sem_value(args.arg1 as u32).unwrap_or(usize::MAX)),
+        7 => {
+            print_semaphores();
+            context.set_rax(0)
+        }
         _ => context.set_rax(usize::MAX),
     }
 }
 
+pub fn sys_mutex(args: &SyscallArgs, context: &mut ProcessContext) {
+    match args.arg0 {
+        0 => context.set_rax(new_mutex(args.arg1 as u32)),
+        1 => context.set_rax(remove_mutex(args.arg1 as u32)),
+        2 => mutex_lock(args.arg1 as u32, context),
+        3 => context.set_rax(mutex_unlock(args.arg1 as u32)),
+        _ => context.set_rax(usize::MAX),
+    }
+}
+
+pub fn sys_shm(args: &SyscallArgs) -> usize {
+    match args.arg0 {
+        0 if shm_create(args.arg1 as u32, args.arg2 as u64) => 0,
+        1 => {
+            // 0 lets the kernel pick the address
+            let addr = match args.arg2 {
+                0 => None,
+                addr => match VirtAddr::try_new(addr as u64) {
+                    Ok(addr) => Some(addr),
+                    Err(_) => return usize::MAX,
+                },
+            };
+            shm_attach(args.arg1 as u32, addr).map_or(usize::MAX, |addr| addr.as_u64() as usize)
+        }
+        2 => match VirtAddr::try_new(args.arg1 as u64) {
+            Ok(addr) if shm_detach(addr) => 0,
+            _ => usize::MAX,
+        },
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_mmap(args: &SyscallArgs) -> usize {
+    // 0 lets the kernel pick the address
+    let addr = match args.arg0 {
+        0 => None,
+        addr => match VirtAddr::try_new(addr as u64) {
+            Ok(addr) => Some(addr),
+            Err(_) => return usize::MAX,
+        },
+    };
+
+    // the flags are passed above the protection
+    let prot = args.arg2 & ((1 << MAP_FLAGS_SHIFT) - 1);
+    let flags = args.arg2 >> MAP_FLAGS_SHIFT;
+    mmap(addr, args.arg1 as u64, prot, flags).map_or(usize::MAX, |addr| addr.as_u64() as usize)
+}
+
+pub fn sys_munmap(args: &SyscallArgs) -> usize {
+    match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) if munmap(addr, args.arg1 as u64) => 0,
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_mprotect(args: &SyscallArgs) -> usize {
+    match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) if mprotect(addr, args.arg1 as u64, args.arg2) => 0,
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_futex(args: &SyscallArgs, context: &mut ProcessContext) {
+    // the word may not be touched yet
+    if !UserPtr::<u32>::new(args.arg0).is_readable() {
+        return context.set_rax(usize::MAX);
+    }
+
+    match args.arg1 {
+        FUTEX_WAIT => futex_wait(args.arg0, args.arg2 as u32, context),
+        FUTEX_WAKE => context.set_rax(futex_wake(args.arg0, args.arg2).unwrap_or(usize::MAX)),
+        _ => context.set_rax(usize::MAX),
+    }
+}
+
+pub fn sys_mq_open(args: &SyscallArgs) -> usize {
+    if mq_open(args.arg0 as u32, args.arg1, args.arg2) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_mq_unlink(args: &SyscallArgs) -> usize {
+    if mq_remove(args.arg0 as u32) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_mq_send(args: &SyscallArgs, context: &mut ProcessContext) {
+    if args.arg2 > MQ_MAX_MSG_SIZE {
+        return context.set_rax(usize::MAX);
+    }
+    let Some(buf) = UserSlice::new(args.arg1, args.arg2).read() else {
+        return context.set_rax(usize::MAX);
+    };
+
+    // the priority is passed above the key
+    let key = args.arg0 as u32;
+    let priority = args.arg0 >> 32;
+    mq_send(key, priority, &buf, context);
+}
+
+pub fn sys_mq_recv(args: &SyscallArgs, context: &mut ProcessContext) {
+    // no message is longer than the largest size
+    let user_buf = UserSlice::new(args.arg1, args.arg2).truncate(MQ_MAX_MSG_SIZE);
+    if !user_buf.is_writable() {
+        return context.set_rax(usize::MAX);
+    }
+
+    let mut buf = vec![0; user_buf.len()];
+    if let Some(len) = mq_recv(args.arg0 as u32, &mut buf, context)
+        && !user_buf.write(&buf[..len])
+    {
+        context.set_rax(usize::MAX);
+    }
+}
+
 pub fn exit_process(args: &SyscallArgs, context: &mut ProcessContext) {
     // DONE: exit process with retcode
     process_exit(args.arg0 as isize, context);
@@ -86,49 +340,44 @@ pub fn list_process() {
 }
 
 pub fn list_dir(args: &SyscallArgs) {
-    if args.arg1 > 0x100 {
+    if args.arg1 > PATH_MAX {
         warn!("list_dir: path too long");
         return;
     }
 
-    let Some(path) = as_user_str(args.arg0, args.arg1) else {
+    let Some(path) = UserSlice::new(args.arg0, args.arg1).read_str() else {
         warn!("list_dir: path not exist");
         return;
     };
 
-    crate::filesystem::ls(path);
+    crate::filesystem::ls(&path);
 }
 
 pub fn sys_allocate(args: &SyscallArgs) -> usize {
-    let layout = unsafe { (args.arg0 as *const Layout).as_ref().unwrap() };
+    // the layout is passed by value, an invalid one is refused
+    let Ok(layout) = Layout::from_size_align(args.arg0, args.arg1) else {
+        return 0;
+    };
 
     if layout.size() == 0 {
         return 0;
     }
 
-    let ret = crate::memory::user::USER_ALLOCATOR
-        .lock()
-        .allocate_first_fit(*layout);
-
-    match ret {
-        Ok(ptr) => ptr.as_ptr() as usize,
-        Err(_) => 0,
-    }
+    allocate(layout).map_or(0, |addr| addr.as_u64() as usize)
 }
 
 pub fn sys_deallocate(args: &SyscallArgs) {
-    let layout = unsafe { (args.arg1 as *const Layout).as_ref().unwrap() };
+    let Ok(layout) = Layout::from_size_align(args.arg1, args.arg2) else {
+        return;
+    };
 
     if args.arg0 == 0 || layout.size() == 0 {
         return;
     }
 
-    let ptr = args.arg0 as *mut u8;
-
-    unsafe {
-        crate::memory::user::USER_ALLOCATOR
-            .lock()
-            .deallocate(core::ptr::NonNull::new_unchecked(ptr), *layout);
+    match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) if deallocate(addr, layout) => {}
+        _ => warn!("syscall: invalid deallocation of {:#x}", args.arg0),
     }
 }
 
@@ -136,18 +385,55 @@ pub fn sys_fork(context: &mut ProcessContext) {
     fork(context)
 }
 
-pub fn sys_open(args: &SyscallArgs) -> usize {
-    let path = match as_user_str(args.arg0, args.arg1) {
+pub fn sys_open(args: &SyscallArgs, context: &mut ProcessContext) {
+    let path = match read_path(args.arg0, args.arg1) {
         Some(path) => path,
-        None => return 0,
+        None => return context.set_rax(0),
     };
 
-    match open(path) {
+    if open(&path, args.arg2, context).is_none() {
+        warn!("sys_open: failed to open {path}");
+    }
+}
+
+pub fn sys_mkfifo(args: &SyscallArgs) -> usize {
+    match read_path(args.arg0, args.arg1) {
+        Some(path) if mkfifo(&path) => 0,
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_ioctl(args: &SyscallArgs) -> usize {
+    // the user memory is touched before any process lock is taken,
+    // faulting its pages in takes the process lock
+    let ptr = UserPtr::<Termios>::new(args.arg2);
+    let mut termios = match args.arg1 {
+        TCGETS => Termios::default(),
+        TCSETS => match ptr.read() {
+            Some(termios) => termios,
+            None => return usize::MAX,
+        },
+        _ => return usize::MAX,
+    };
+
+    let ret = ioctl(args.arg0 as u8, args.arg1, &mut termios);
+    if ret >= 0 && args.arg1 == TCGETS && !ptr.write(termios) {
+        return usize::MAX;
+    }
+    ret as usize
+}
+
+pub fn sys_dup(args: &SyscallArgs) -> usize {
+    match dup(args.arg0 as u8) {
         Some(fd) => fd as usize,
-        None => {
-            warn!("sys_open: failed to open {path}");
-            0
-        }
+        None => usize::MAX,
+    }
+}
+
+pub fn sys_dup2(args: &SyscallArgs) -> usize {
+    match dup2(args.arg0 as u8, args.arg1 as u8) {
+        Some(fd) => fd as usize,
+        None => usize::MAX,
     }
 }
 
@@ -159,10 +445,21 @@ pub fn sys_brk(args: &SyscallArgs) -> usize {
     let new_heap_end = if args.arg0 == 0 {
         None
     } else {
-        Some(VirtAddr::new(args.arg0 as u64))
+        match VirtAddr::try_new(args.arg0 as u64) {
+            Ok(addr) => Some(addr),
+            Err(_) => return !0,
+        }
     };
     match brk(new_heap_end) {
         Some(new_heap_end) => new_heap_end.as_u64() as usize,
         None => !0,
     }
 }
+
+/// Copy a path in, it is refused if it is too long
+fn read_path(addr: usize, len: usize) -> Option<String> {
+    if len > PATH_MAX {
+        return None;
+    }
+    UserSlice::new(addr, len).read_str()
+}
@@ -44,9 +44,10 @@ pub fn init(boot_info: &'static BootInfo) {
     memory::address::init(boot_info);
     memory::gdt::init(); // init gdt
     memory::allocator::init(); // init kernel heap allocator
-    interrupt::init(); // init interrupts
+    interrupt::init(boot_info); // init interrupts
+    rtc::init(); // init wall-clock time
     memory::init(boot_info); // init memory manager
-    memory::user::init(); // init user memory manager
+    memory::init_protection(); // enable SMEP and SMAP
     proc::init(boot_info); // init process manager
     filesystem::init(); // init filesystem
 
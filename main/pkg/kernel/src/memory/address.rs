@@ -35,30 +35,17 @@ pub fn is_user_accessable(addr: usize) -> bool {
     }
 }
 
-pub fn as_user_str(ptr: usize, len: usize) -> Option<&'static str> {
-    match core::str::from_utf8(as_user_slice(ptr, len)?) {
-        Ok(s) => Some(s),
-        Err(_) => {
-            warn!("syscall: invalid utf8 string");
-            None
+/// The physical address behind a user address in the current page table
+pub fn user_to_physical(addr: usize) -> Option<u64> {
+    let mapper = &mut PageTableContext::new().mapper();
+    match mapper.translate(VirtAddr::try_new(addr as u64).ok()?) {
+        Mapped {
+            frame,
+            offset,
+            flags,
+        } if flags.contains(PageTableFlags::USER_ACCESSIBLE) => {
+            Some(frame.start_address().as_u64() + offset)
         }
+        _ => None,
     }
 }
-
-pub fn as_user_slice<'a>(ptr: usize, len: usize) -> Option<&'a [u8]> {
-    if !is_user_accessable(ptr) {
-        warn!("syscall: invalid access to {:#x}", ptr);
-        return None;
-    }
-
-    unsafe { Some(core::slice::from_raw_parts(ptr as *const u8, len)) }
-}
-
-pub fn as_user_slice_mut<'a>(ptr: usize, len: usize) -> Option<&'a mut [u8]> {
-    if !is_user_accessable(ptr) {
-        warn!("syscall: invalid access to {:#x}", ptr);
-        return None;
-    }
-
-    unsafe { Some(core::slice::from_raw_parts_mut(ptr as *mut u8, len)) }
-}
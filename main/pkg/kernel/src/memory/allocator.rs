@@ -1,12 +1,22 @@
+use alloc::alloc::{GlobalAlloc, Layout};
+use alloc::format;
+use alloc::string::String;
+use core::ptr::{addr_of_mut, null_mut, NonNull};
 use linked_list_allocator::LockedHeap;
-use x86_64::VirtAddr;
-use core::ptr::addr_of_mut;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::{get_frame_alloc, physical_to_virtual, PAGE_SIZE, PHYSICAL_OFFSET};
 
 pub const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
 
-/// Use linked_list_allocator for kernel heap
+/// Object sizes of the slab caches, larger objects go to the linked list
+const SLAB_SIZES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Slab caches for small objects in front of linked_list_allocator
 #[global_allocator]
-pub static ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static ALLOCATOR: KernelAllocator = KernelAllocator::new();
 
 pub fn init() {
     // static buffer for kernel heap
@@ -17,7 +27,7 @@ pub fn init() {
     let heap_end = heap_start + HEAP_SIZE as u64;
 
     unsafe {
-        ALLOCATOR.lock().init(addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE);
+        ALLOCATOR.heap.lock().init(addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE);
     }
 
     debug!(
@@ -32,6 +42,209 @@ pub fn init() {
     info!("Kernel Heap Initialized.");
 }
 
+/// A cache of same-sized objects carved out of whole frames
+///
+/// free objects are linked through their first word,
+/// frames stay in the cache once they are taken
+struct SlabCache {
+    size: usize,
+    free: *mut u8,
+    frames: usize,
+    used: usize,
+}
+
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    const fn new(size: usize) -> Self {
+        Self {
+            size,
+            free: null_mut(),
+            frames: 0,
+            used: 0,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.frames * PAGE_SIZE as usize / self.size
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        if self.free.is_null() && !self.grow() {
+            return None;
+        }
+
+        let obj = self.free;
+        self.free = unsafe { *(obj as *mut *mut u8) };
+        self.used += 1;
+        Some(obj)
+    }
+
+    fn push(&mut self, obj: *mut u8) {
+        unsafe { *(obj as *mut *mut u8) = self.free };
+        self.free = obj;
+        self.used -= 1;
+    }
+
+    /// Take a frame and split it into objects
+    ///
+    /// fails while the frame allocator is not ready, or is held by
+    /// whoever is allocating, the linked list is used then
+    fn grow(&mut self) -> bool {
+        let Some(frame) = get_frame_alloc().and_then(|mut alloc| alloc.allocate_frame()) else {
+            return false;
+        };
+
+        let start = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+        for offset in (0..PAGE_SIZE as usize).step_by(self.size).rev() {
+            let obj = unsafe { start.add(offset) };
+            unsafe { *(obj as *mut *mut u8) = self.free };
+            self.free = obj;
+        }
+
+        self.frames += 1;
+        true
+    }
+}
+
+/// The kernel heap
+///
+/// - small objects come from the slab caches, which grow with frames
+///   from the frame allocator
+/// - everything else comes from the linked list in the static heap,
+///   and from contiguous frames once the static heap is full
+pub struct KernelAllocator {
+    heap: LockedHeap,
+    slabs: Mutex<[SlabCache; SLAB_SIZES.len()]>,
+    /// frames handed out for large objects
+    large_frames: Mutex<usize>,
+}
+
+impl KernelAllocator {
+    const fn new() -> Self {
+        let mut slabs = [const { SlabCache::new(0) }; SLAB_SIZES.len()];
+        let mut i = 0;
+        while i < SLAB_SIZES.len() {
+            slabs[i] = SlabCache::new(SLAB_SIZES[i]);
+            i += 1;
+        }
+
+        Self {
+            heap: LockedHeap::empty(),
+            slabs: Mutex::new(slabs),
+            large_frames: Mutex::new(0),
+        }
+    }
+
+    /// The cache for the layout, objects are aligned to their size
+    fn slab_index(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SLAB_SIZES.iter().position(|&slab| size <= slab)
+    }
+
+    /// Order of the frames for an object too large for the static heap
+    fn large_order(layout: &Layout) -> usize {
+        let frames = layout.size().div_ceil(PAGE_SIZE as usize);
+        frames.next_power_of_two().trailing_zeros() as usize
+    }
+
+    fn in_static_heap(&self, ptr: *mut u8) -> bool {
+        let heap = self.heap.lock();
+        (heap.bottom()..heap.top()).contains(&ptr)
+    }
+
+    fn alloc_large(&self, layout: &Layout) -> *mut u8 {
+        let order = Self::large_order(layout);
+        let Some(frame) = get_frame_alloc().and_then(|mut alloc| alloc.allocate_contiguous(order))
+        else {
+            return null_mut();
+        };
+
+        *self.large_frames.lock() += 1 << order;
+        physical_to_virtual(frame.start_address().as_u64()) as *mut u8
+    }
+
+    unsafe fn dealloc_large(&self, ptr: *mut u8, layout: &Layout) {
+        let order = Self::large_order(layout);
+        let phys = ptr as u64 - PHYSICAL_OFFSET.get().unwrap();
+        let frame = PhysFrame::containing_address(PhysAddr::new(phys));
+
+        match get_frame_alloc() {
+            Some(mut alloc) => unsafe { alloc.deallocate_contiguous(frame, order) },
+            None => {
+                warn!("Frame allocator is busy, leaking {:#x} of order {}", phys, order);
+                return;
+            }
+        }
+        *self.large_frames.lock() -= 1 << order;
+    }
+
+    /// Usage of the static heap, the large objects and every slab cache
+    ///
+    /// the numbers are taken before formatting, which allocates
+    pub fn usage(&self) -> String {
+        let (used, size) = {
+            let heap = self.heap.lock();
+            (heap.used(), heap.size())
+        };
+        let large_frames = *self.large_frames.lock();
+        let slabs = self
+            .slabs
+            .lock()
+            .each_ref()
+            .map(|slab| (slab.size, slab.used, slab.capacity()));
+
+        let (used, used_unit) = crate::humanized_size(used as u64);
+        let (size, size_unit) = crate::humanized_size(size as u64);
+        let mut output = format!(
+            "Heap   : {:>6.2} {:>3} / {:>6.2} {:>3}, large objects in {} frames\n",
+            used, used_unit, size, size_unit, large_frames
+        );
+
+        // objects in use and objects that fit in the frames of every cache
+        output += "Slab   :";
+        for (size, used, capacity) in slabs {
+            output += format!(" {}B {}/{}", size, used, capacity).as_str();
+        }
+        output += "\n";
+        output
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let slab = Self::slab_index(&layout);
+        if let Some(index) = slab
+            && let Some(obj) = self.slabs.lock()[index].pop()
+        {
+            return obj;
+        }
+
+        let ret = self.heap.lock().allocate_first_fit(layout);
+        match ret {
+            Ok(ptr) => ptr.as_ptr(),
+            // small objects are only freed to a cache or to the linked list
+            Err(()) if slab.is_none() && layout.align() <= PAGE_SIZE as usize => {
+                self.alloc_large(&layout)
+            }
+            Err(()) => null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if self.in_static_heap(ptr) {
+            let ptr = unsafe { NonNull::new_unchecked(ptr) };
+            unsafe { self.heap.lock().deallocate(ptr, layout) };
+            return;
+        }
+
+        match Self::slab_index(&layout) {
+            Some(index) => self.slabs.lock()[index].push(ptr),
+            None => unsafe { self.dealloc_large(ptr, &layout) },
+        }
+    }
+}
+
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("Allocation error: {:?}", layout);
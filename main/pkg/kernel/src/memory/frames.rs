@@ -1,82 +1,263 @@
+use alloc::vec;
 use alloc::vec::Vec;
-use alloc::boxed::Box;
 use boot::{MemoryMap, MemoryType};
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
 use x86_64::PhysAddr;
 
-once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);
+use super::{physical_to_virtual, PAGE_SIZE};
+
+once_mutex!(pub FRAME_ALLOCATOR: BuddyFrameAllocator);
 
 guard_access_fn! {
-    pub get_frame_alloc(FRAME_ALLOCATOR: BootInfoFrameAllocator)
+    pub get_frame_alloc(FRAME_ALLOCATOR: BuddyFrameAllocator)
 }
 
-type BootInfoFrameIter = Box<dyn Iterator<Item = PhysFrame> + Send>;
+/// Largest block has 2^MAX_ORDER frames, 4 MiB
+pub const MAX_ORDER: usize = 10;
+
+/// End of a free list
+const NIL: u64 = u64::MAX;
+
+/// Links of a free block, kept in the first frame of the block
+#[repr(C)]
+struct FreeBlock {
+    prev: u64,
+    next: u64,
+}
 
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    size: usize,
-    used: usize,
-    frames: BootInfoFrameIter,
-    recycled: Vec<PhysFrame>,
+/// A buddy allocator over the usable frames of the bootloader's memory map.
+///
+/// a block of order `n` has 2^n frames and is aligned to its size,
+/// freed blocks are merged with their buddy as long as it is free too
+pub struct BuddyFrameAllocator {
+    /// address of the first block of every order
+    base: u64,
+    /// end of the managed range
+    end: u64,
+    /// first free block of every order
+    heads: [u64; MAX_ORDER + 1],
+    /// one bit per block of every order, set when the block is free
+    bitmaps: [Vec<u64>; MAX_ORDER + 1],
+    /// number of free blocks of every order
+    free_blocks: [usize; MAX_ORDER + 1],
+    total: usize,
+    free: usize,
 }
 
-impl BootInfoFrameAllocator {
+impl BuddyFrameAllocator {
     /// Create a FrameAllocator from the passed memory map.
     ///
     /// This function is unsafe because the caller must guarantee that the passed
     /// memory map is valid. The main requirement is that all frames that are marked
     /// as `USABLE` in it are really unused.
-    pub unsafe fn init(memory_map: &MemoryMap, size: usize) -> Self {
-        BootInfoFrameAllocator {
-            size,
-            used: 0,
-            frames: create_frame_iter(memory_map),
-            recycled: Vec::new(),
+    pub unsafe fn init(memory_map: &MemoryMap) -> Self {
+        let usable = || {
+            memory_map
+                .iter()
+                .filter(|r| r.ty == MemoryType::CONVENTIONAL)
+                .map(|r| (r.phys_start, r.phys_start + r.page_count * PAGE_SIZE))
+        };
+
+        let max_block = PAGE_SIZE << MAX_ORDER;
+        let base = usable().map(|(start, _)| start).min().unwrap_or(0) & !(max_block - 1);
+        let end = usable().map(|(_, end)| end).max().unwrap_or(0).next_multiple_of(max_block);
+
+        let mut allocator = Self {
+            base,
+            end,
+            heads: [NIL; MAX_ORDER + 1],
+            bitmaps: core::array::from_fn(|order| {
+                let blocks = (end - base) / (PAGE_SIZE << order);
+                vec![0; blocks.div_ceil(64) as usize]
+            }),
+            free_blocks: [0; MAX_ORDER + 1],
+            total: 0,
+            free: 0,
+        };
+
+        for (start, end) in usable() {
+            allocator.total += ((end - start) / PAGE_SIZE) as usize;
+
+            // free the largest aligned blocks that fit
+            let mut addr = start;
+            while addr < end {
+                let order = (0..=MAX_ORDER)
+                    .rev()
+                    .find(|&order| {
+                        let size = PAGE_SIZE << order;
+                        (addr - base) % size == 0 && addr + size <= end
+                    })
+                    .unwrap_or(0);
+
+                unsafe {
+                    allocator.deallocate_contiguous(
+                        PhysFrame::containing_address(PhysAddr::new(addr)),
+                        order,
+                    )
+                };
+                addr += PAGE_SIZE << order;
+            }
         }
+
+        allocator
     }
 
     pub fn frames_used(&self) -> usize {
-        self.used
+        self.total - self.free
+    }
+
+    pub fn frames_free(&self) -> usize {
+        self.free
     }
 
     pub fn frames_total(&self) -> usize {
-        self.size
+        self.total
     }
-    
-    pub fn frames_recycled(&self) -> usize {
-        self.recycled.len()
+
+    /// Number of free blocks of every order
+    pub fn free_blocks(&self) -> &[usize; MAX_ORDER + 1] {
+        &self.free_blocks
     }
-}
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        // Try to recycle a frame first.
-        if let Some(frame) = self.recycled.pop() {
-            Some(frame)
+    /// Allocate 2^order physically contiguous frames, aligned to their size
+    pub fn allocate_contiguous(&mut self, order: usize) -> Option<PhysFrame> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        let (addr, found) = (order..=MAX_ORDER).find_map(|k| Some((self.pop(k)?, k)))?;
+
+        // split the block, the upper halves are left free
+        for k in (order..found).rev() {
+            self.push(addr + (PAGE_SIZE << k), k);
+        }
+
+        self.free -= 1 << order;
+        Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+
+    /// Free 2^order frames from `allocate_contiguous`
+    ///
+    /// frames of a block may also be freed one by one
+    pub unsafe fn deallocate_contiguous(&mut self, frame: PhysFrame, order: usize) {
+        let mut addr = frame.start_address().as_u64();
+        if !self.is_managed(addr, order) {
+            warn!("Frame {:#x} of order {} is not managed.", addr, order);
+            return;
+        }
+        // the block, or a larger block holding it, is already free
+        if (order..=MAX_ORDER).any(|k| self.is_free(self.block_of(addr, k), k)) {
+            warn!("Frame {:#x} of order {} is freed twice.", addr, order);
+            return;
+        }
+
+        self.free += 1 << order;
+
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = self.base + ((addr - self.base) ^ (PAGE_SIZE << order));
+            if !self.is_free(buddy, order) {
+                break;
+            }
+            self.remove(buddy, order);
+            addr = addr.min(buddy);
+            order += 1;
+        }
+
+        self.push(addr, order);
+    }
+
+    /// Whether the block is aligned to its size and in the managed range
+    fn is_managed(&self, addr: u64, order: usize) -> bool {
+        order <= MAX_ORDER
+            && addr >= self.base
+            && (addr - self.base).is_multiple_of(PAGE_SIZE << order)
+            && addr
+                .checked_add(PAGE_SIZE << order)
+                .is_some_and(|end| end <= self.end)
+    }
+
+    /// The block of the order that holds the frame at `addr`
+    fn block_of(&self, addr: u64, order: usize) -> u64 {
+        self.base + ((addr - self.base) & !((PAGE_SIZE << order) - 1))
+    }
+
+    fn link(addr: u64) -> *mut FreeBlock {
+        physical_to_virtual(addr) as *mut FreeBlock
+    }
+
+    /// Bitmap word and bit of a block
+    fn bit(&self, addr: u64, order: usize) -> (usize, u64) {
+        let index = ((addr - self.base) / (PAGE_SIZE << order)) as usize;
+        (index / 64, 1 << (index % 64))
+    }
+
+    fn is_free(&self, addr: u64, order: usize) -> bool {
+        let (word, mask) = self.bit(addr, order);
+        self.bitmaps[order]
+            .get(word)
+            .is_some_and(|bits| bits & mask != 0)
+    }
+
+    fn set_free(&mut self, addr: u64, order: usize, free: bool) {
+        let (word, mask) = self.bit(addr, order);
+        if free {
+            self.bitmaps[order][word] |= mask;
         } else {
-            self.used += 1;
-            self.frames.next()
+            self.bitmaps[order][word] &= !mask;
         }
     }
+
+    fn push(&mut self, addr: u64, order: usize) {
+        let next = self.heads[order];
+        unsafe {
+            Self::link(addr).write(FreeBlock { prev: NIL, next });
+            if next != NIL {
+                (*Self::link(next)).prev = addr;
+            }
+        }
+
+        self.heads[order] = addr;
+        self.set_free(addr, order, true);
+        self.free_blocks[order] += 1;
+    }
+
+    fn remove(&mut self, addr: u64, order: usize) {
+        unsafe {
+            let FreeBlock { prev, next } = Self::link(addr).read();
+            if prev != NIL {
+                (*Self::link(prev)).next = next;
+            } else {
+                self.heads[order] = next;
+            }
+            if next != NIL {
+                (*Self::link(next)).prev = prev;
+            }
+        }
+
+        self.set_free(addr, order, false);
+        self.free_blocks[order] -= 1;
+    }
+
+    fn pop(&mut self, order: usize) -> Option<u64> {
+        let addr = self.heads[order];
+        if addr == NIL {
+            return None;
+        }
+        self.remove(addr, order);
+        Some(addr)
+    }
 }
 
-impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
-    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
-        // DONE: deallocate frame (not for lab 2)
-        self.recycled.push(frame);
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate_contiguous(0)
     }
 }
 
-fn create_frame_iter(memory_map: &MemoryMap) -> BootInfoFrameIter {
-    let iter = memory_map
-        .clone()
-        .into_iter()
-        // get usable regions from memory map
-        .filter(|r| r.ty == MemoryType::CONVENTIONAL)
-        // align to page boundary
-        .flat_map(|r| (0..r.page_count).map(move |v| (v * 4096 + r.phys_start)))
-        // create `PhysFrame` types from the start addresses
-        .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)));
-
-    Box::new(iter)
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        // DONE: deallocate frame (not for lab 2)
+        unsafe { self.deallocate_contiguous(frame, 0) };
+    }
 }
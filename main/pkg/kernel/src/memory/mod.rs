@@ -6,6 +6,7 @@ pub mod gdt;
 
 pub use address::*;
 pub use frames::*;
+pub use user::*;
 
 use crate::humanized_size;
 
@@ -29,10 +30,7 @@ pub fn init(boot_info: &'static boot::BootInfo) {
     info!("Free Usable Memory : {:>7.*} {}", 3, size, unit);
 
     unsafe {
-        init_FRAME_ALLOCATOR(BootInfoFrameAllocator::init(
-            memory_map,
-            usable_mem_size as usize,
-        ));
+        init_FRAME_ALLOCATOR(BuddyFrameAllocator::init(memory_map));
     }
 
     info!("Frame Allocator initialized.");
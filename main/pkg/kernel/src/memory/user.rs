@@ -1,60 +1,255 @@
-use crate::proc::PageTableContext;
-use linked_list_allocator::LockedHeap;
-use x86_64::structures::paging::{
-    mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
-};
+use alloc::{string::String, vec, vec::Vec};
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86::bits64::rflags::{clac, stac};
+use x86::cpuid::CpuId;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::structures::paging::mapper::TranslateResult;
+use x86_64::structures::paging::{Page, PageTableFlags, Translate};
 use x86_64::VirtAddr;
 
-pub const USER_HEAP_START: usize = 0x4000_0000_0000;
-pub const USER_HEAP_SIZE: usize = 1024 * 1024; // 1 MiB
-const USER_HEAP_PAGE: usize = USER_HEAP_SIZE / crate::memory::PAGE_SIZE as usize;
+use crate::proc::PageTableContext;
+
+/// User addresses are in the lower half
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
+
+/// Set once SMAP is enabled, `stac` and `clac` are undefined without it
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enable SMEP and SMAP if the CPU supports them
+///
+/// - SMEP: the kernel never executes user pages
+/// - SMAP: the kernel only touches user pages inside `with_user_access`
+pub fn init_protection() {
+    let features = CpuId::new().get_extended_feature_info();
+    let smep = features.as_ref().is_some_and(|f| f.has_smep());
+    let smap = features.as_ref().is_some_and(|f| f.has_smap());
+
+    let mut flags = Cr4Flags::empty();
+    if smep {
+        flags |= Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION;
+    }
+    if smap {
+        flags |= Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION;
+    }
+    unsafe { Cr4::update(|f| f.insert(flags)) };
+    SMAP_ENABLED.store(smap, Ordering::Relaxed);
+
+    info!("SMEP: {}, SMAP: {}", smep, smap);
+}
+
+/// Run `f` with access to user pages allowed
+pub fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    let smap = SMAP_ENABLED.load(Ordering::Relaxed);
+    if smap {
+        unsafe { stac() };
+    }
+    let ret = f();
+    if smap {
+        unsafe { clac() };
+    }
+    ret
+}
+
+// `copy_user(dst, src, len)` returns the bytes left uncopied,
+// a fault on `rep movsb` that cannot be handled resumes at the fixup
+core::arch::global_asm!(
+    ".global copy_user",
+    "copy_user:",
+    "mov rcx, rdx",
+    ".global copy_user_fault",
+    "copy_user_fault:",
+    "rep movsb",
+    ".global copy_user_fixup",
+    "copy_user_fixup:",
+    "mov rax, rcx",
+    "ret",
+);
+
+unsafe extern "C" {
+    fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    static copy_user_fault: u8;
+    static copy_user_fixup: u8;
+}
+
+/// Where to resume after a page fault at `ip` the kernel cannot handle,
+/// only faults while copying user memory are recovered
+pub fn fault_fixup(ip: VirtAddr) -> Option<VirtAddr> {
+    let fault = VirtAddr::from_ptr(&raw const copy_user_fault);
+    (ip == fault).then(|| VirtAddr::from_ptr(&raw const copy_user_fixup))
+}
+
+/// Whether the range is canonical and in the user half
+pub fn is_user_range(addr: usize, len: usize) -> bool {
+    addr.checked_add(len)
+        .is_some_and(|end| end as u64 <= USER_END)
+}
+
+/// Plain data that can be copied from and to user memory
+///
+/// every bit pattern must be a valid value, and there is no padding
+pub unsafe trait UserData: Copy {}
 
-pub static USER_ALLOCATOR: LockedHeap = LockedHeap::empty();
+unsafe impl UserData for u32 {}
+unsafe impl UserData for u64 {}
+unsafe impl UserData for isize {}
+unsafe impl UserData for syscall_def::time::TimeSpec {}
+unsafe impl UserData for syscall_def::tty::Termios {}
 
-// NOTE: export mod user / call in the kernel init / after frame allocator
-pub fn init() {
-    init_user_heap().expect("User Heap Initialization Failed.");
-    info!("User Heap Initialized.");
+/// A range of the current process's memory, checked before every copy
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
 }
 
-pub fn init_user_heap() -> Result<(), MapToError<Size4KiB>> {
-    // Get current pagetable mapper
-    let mapper = &mut PageTableContext::new().mapper();
-    // Get global frame allocator
-    let frame_allocator = &mut *super::get_frame_alloc_for_sure();
+impl UserSlice {
+    pub const fn new(addr: usize, len: usize) -> Self {
+        Self { addr, len }
+    }
 
-    // DONE: use elf::map_range to allocate & map
-    //        frames (R/W/User Access)
-    let page_range = {
-        let heap_start = VirtAddr::new(USER_HEAP_START as u64);
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = heap_start_page + USER_HEAP_PAGE as u64 - 1u64;
-        Page::range(heap_start_page, heap_end_page)
-    };
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-    debug!(
-        "User Heap        : 0x{:016x}-0x{:016x}",
-        page_range.start.start_address().as_u64(),
-        page_range.end.start_address().as_u64()
-    );
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
-    let (size, unit) = crate::humanized_size(USER_HEAP_SIZE as u64);
-    info!("User Heap Size   : {:>7.*} {}", 3, size, unit);
+    /// The first `len` bytes of the range
+    pub fn truncate(self, len: usize) -> Self {
+        Self::new(self.addr, self.len.min(len))
+    }
 
-    for page in page_range {
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags =
-            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    pub fn is_readable(&self) -> bool {
+        self.check(false)
     }
 
-    unsafe {
-        USER_ALLOCATOR
-            .lock()
-            .init(USER_HEAP_START as *mut u8, USER_HEAP_SIZE);
+    pub fn is_writable(&self) -> bool {
+        self.check(true)
     }
 
-    Ok(())
+    /// Check the range is in the user half and mapped with the permissions,
+    /// untouched pages of the stack and of mappings are faulted in
+    fn check(&self, write: bool) -> bool {
+        if !is_user_range(self.addr, self.len) {
+            return false;
+        }
+        if self.len == 0 {
+            return true;
+        }
+
+        let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let mut err_code = PageFaultErrorCode::USER_MODE;
+        if write {
+            required |= PageTableFlags::WRITABLE;
+            err_code |= PageFaultErrorCode::CAUSED_BY_WRITE;
+        }
+
+        let start: Page = Page::containing_address(VirtAddr::new(self.addr as u64));
+        let end = Page::containing_address(VirtAddr::new((self.addr + self.len - 1) as u64));
+
+        Page::range_inclusive(start, end).all(|page| {
+            let addr = page.start_address();
+            let flags = || match PageTableContext::new().mapper().translate(addr) {
+                TranslateResult::Mapped { flags, .. } => Some(flags),
+                _ => None,
+            };
+
+            let flags = match flags() {
+                Some(flags) => flags,
+                None if crate::proc::handle_page_fault(addr, err_code) => {
+                    flags().unwrap_or(PageTableFlags::empty())
+                }
+                None => PageTableFlags::empty(),
+            };
+            flags.contains(required)
+        })
+    }
+
+    /// Copy the range into `dst`, which has `len` bytes
+    fn copy_in(&self, dst: *mut u8) -> bool {
+        self.is_readable()
+            && with_user_access(|| unsafe { copy_user(dst, self.addr as *const u8, self.len) }) == 0
+    }
+
+    /// Copy the range into a kernel buffer
+    pub fn read(&self) -> Option<Vec<u8>> {
+        let mut buf = vec![0; self.len];
+        self.copy_in(buf.as_mut_ptr()).then_some(buf)
+    }
+
+    pub fn read_str(&self) -> Option<String> {
+        match String::from_utf8(self.read()?) {
+            Ok(s) => Some(s),
+            Err(_) => {
+                warn!("syscall: invalid utf8 string");
+                None
+            }
+        }
+    }
+
+    /// Copy `data` to the start of the range, which must be large enough
+    pub fn write(&self, data: &[u8]) -> bool {
+        if data.len() > self.len {
+            return false;
+        }
+
+        let dst = self.truncate(data.len());
+        dst.is_writable()
+            && with_user_access(|| unsafe {
+                copy_user(dst.addr as *mut u8, data.as_ptr(), data.len())
+            }) == 0
+    }
+}
+
+/// A value in the current process's memory
+#[derive(Debug, Clone, Copy)]
+pub struct UserPtr<T: UserData> {
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: UserData> UserPtr<T> {
+    pub const fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn slice(&self) -> UserSlice {
+        UserSlice::new(self.addr, size_of::<T>())
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.slice().is_readable()
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.slice().is_writable()
+    }
+
+    pub fn read(&self) -> Option<T> {
+        let mut value = MaybeUninit::<T>::uninit();
+        if !self.slice().copy_in(value.as_mut_ptr() as *mut u8) {
+            warn!("syscall: invalid read from {:#x}", self.addr);
+            return None;
+        }
+        // any bytes make a valid `T`
+        Some(unsafe { value.assume_init() })
+    }
+
+    pub fn write(&self, value: T) -> bool {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>())
+        };
+        if !self.slice().write(bytes) {
+            warn!("syscall: invalid write to {:#x}", self.addr);
+            return false;
+        }
+        true
+    }
 }
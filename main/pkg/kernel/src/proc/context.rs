@@ -1,8 +1,9 @@
 use volatile::{access::ReadOnly, VolatileRef};
 use x86_64::{registers::rflags::RFlags, structures::idt::InterruptStackFrameValue, VirtAddr};
 use x86_64::structures::gdt::SegmentSelector;
+use x86_64::PrivilegeLevel;
 
-use crate::{memory::gdt::get_user_selector, RegistersValue};
+use crate::{memory::gdt::get_user_selector, memory::is_user_range, RegistersValue};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -38,6 +39,55 @@ impl ProcessContext {
         self.value.stack_frame.stack_pointer += offset;
     }
 
+    /// Rewind the instruction pointer over `int 0x80`
+    ///
+    /// the syscall is issued again once the process is resumed
+    #[inline]
+    pub fn restart_syscall(&mut self) {
+        self.value.stack_frame.instruction_pointer -= 2u64;
+    }
+
+    /// Whether the context returns to user mode
+    #[inline]
+    pub fn is_user(&self) -> bool {
+        self.value.stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3
+    }
+
+    /// Call `entry(arg)` on the given stack when returning to user mode
+    pub fn enter_handler(&mut self, entry: VirtAddr, stack_pointer: VirtAddr, arg: usize) {
+        self.value.stack_frame.instruction_pointer = entry;
+        self.value.stack_frame.stack_pointer = stack_pointer;
+        self.value.regs.rdi = arg;
+    }
+
+    /// Restore the registers saved in user memory
+    ///
+    /// segments and privileged flags are kept, user code cannot change them,
+    /// fails unless the instruction and stack pointers are user addresses
+    pub fn restore_user(&mut self, value: &ProcessContextValue) -> bool {
+        let ip = value.stack_frame.instruction_pointer.as_u64() as usize;
+        let sp = value.stack_frame.stack_pointer.as_u64() as usize;
+        if !is_user_range(ip, 1) || !is_user_range(sp, 1) {
+            return false;
+        }
+
+        let user_flags = RFlags::CARRY_FLAG
+            | RFlags::PARITY_FLAG
+            | RFlags::AUXILIARY_CARRY_FLAG
+            | RFlags::ZERO_FLAG
+            | RFlags::SIGN_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::OVERFLOW_FLAG;
+
+        let frame = &mut self.value.stack_frame;
+        frame.instruction_pointer = value.stack_frame.instruction_pointer;
+        frame.stack_pointer = value.stack_frame.stack_pointer;
+        frame.cpu_flags =
+            (frame.cpu_flags & !user_flags) | (value.stack_frame.cpu_flags & user_flags);
+        self.value.regs = value.regs;
+        true
+    }
+
     #[inline]
     pub fn save(&mut self, context: &ProcessContext) {
         self.value = context.as_ref().as_ptr().read();
@@ -10,7 +10,7 @@ pub struct ProcessData {
     // shared data
     pub(super) env: Arc<RwLock<BTreeMap<String, String>>>,
     pub(super) resources: Arc<RwLock<ResourceSet>>,
-    pub(super) semaphores: Arc<RwLock<SemaphoreSet>>,
+    pub(super) message_queues: Arc<RwLock<MessageQueueSet>>,
 }
 
 impl Default for ProcessData {
@@ -18,7 +18,7 @@ impl Default for ProcessData {
         Self {
             env: Arc::new(RwLock::new(BTreeMap::new())),
             resources: Arc::new(RwLock::new(ResourceSet::default())),
-            semaphores: Arc::new(RwLock::new(SemaphoreSet::default())),
+            message_queues: Arc::new(RwLock::new(MessageQueueSet::default())),
         }
     }
 }
@@ -44,28 +44,88 @@ impl ProcessData {
         self.resources.read().write(fd, buf)
     }
 
-    pub fn open(&mut self, res: Resource) -> u8 {
+    /// Data for a process spawned by this one
+    ///
+    /// fds but the `O_CLOEXEC` ones are inherited,
+    /// stdin and stdout are replaced by `stdio`,
+    /// message queues are shared so that parent and children meet on them
+    pub fn inherit(&self, stdio: (u8, u8)) -> Option<Self> {
+        let parent = self.resources.read();
+        let mut resources = parent.inherited();
+        if !resources.redirect(0, &parent, stdio.0) || !resources.redirect(1, &parent, stdio.1) {
+            return None;
+        }
+
+        Some(Self {
+            resources: Arc::new(RwLock::new(resources)),
+            message_queues: self.message_queues.clone(),
+            ..Self::default()
+        })
+    }
+
+    pub fn pipe(&mut self, cloexec: bool) -> Option<(u8, u8)> {
+        let (read, write) = crate::pipe::pipe();
+        let mut resources = self.resources.write();
+        let read = resources.open(Resource::Pipe(read))?;
+        let Some(write) = resources.open(Resource::Pipe(write)) else {
+            resources.close(read);
+            return None;
+        };
+        resources.set_cloexec(read, cloexec);
+        resources.set_cloexec(write, cloexec);
+        Some((read, write))
+    }
+
+    pub fn open_channel(&self, fd: u8) -> Option<usize> {
+        self.resources.read().open_channel(fd)
+    }
+
+    pub fn wait_channel(&self, fd: u8) -> Option<usize> {
+        self.resources.read().wait_channel(fd)
+    }
+
+    pub fn ioctl(&self, fd: u8, request: usize, termios: &mut Termios) -> isize {
+        self.resources.read().ioctl(fd, request, termios)
+    }
+
+    pub fn open(&mut self, res: Resource) -> Option<u8> {
         self.resources.write().open(res)
     }
+
+    pub fn dup(&mut self, fd: u8) -> Option<u8> {
+        self.resources.write().dup(fd)
+    }
+
+    pub fn dup2(&mut self, fd: u8, new_fd: u8) -> Option<u8> {
+        self.resources.write().dup2(fd, new_fd)
+    }
     
+    pub fn set_nonblocking(&mut self, fd: u8, nonblocking: bool) {
+        self.resources.write().set_nonblocking(fd, nonblocking)
+    }
+
+    pub fn set_cloexec(&mut self, fd: u8, cloexec: bool) {
+        self.resources.write().set_cloexec(fd, cloexec)
+    }
+
     pub fn close(&mut self, fd: u8) -> bool {
         self.resources.write().close(fd)
     }
 
     #[inline]
-    pub fn new_sem(&mut self, key: u32, value: usize) -> bool {
-        self.semaphores.write().insert(key, value)
+    pub fn mq_open(&mut self, key: u32, capacity: usize, msg_size: usize) -> bool {
+        self.message_queues.write().open(key, capacity, msg_size)
     }
     #[inline]
-    pub fn remove_sem(&mut self, key: u32) -> bool {
-        self.semaphores.write().remove(key)
+    pub fn mq_remove(&mut self, key: u32) -> bool {
+        self.message_queues.write().remove(key)
     }
     #[inline]
-    pub fn sem_signal(&mut self, key: u32) -> SemaphoreResult {
-        self.semaphores.read().signal(key)
+    pub fn mq_send(&self, key: u32, priority: usize, buf: &[u8]) -> MessageQueueResult {
+        self.message_queues.read().send(key, priority, buf)
     }
     #[inline]
-    pub fn sem_wait(&mut self, key: u32, pid: ProcessId) -> SemaphoreResult {
-        self.semaphores.read().wait(key, pid)
+    pub fn mq_recv(&self, key: u32, buf: &mut [u8]) -> MessageQueueResult {
+        self.message_queues.read().recv(key, buf)
     }
 }
@@ -4,20 +4,23 @@ use crate::memory::*;
 
 use boot::AppListRef;
 
+use alloc::boxed::Box;
 use alloc::format;
+use core::cmp::Reverse;
 use alloc::collections::*;
 use alloc::sync::Weak;
+use alloc::vec::Vec;
 use spin::{Mutex, RwLock};
 
 pub static PROCESS_MANAGER: spin::Once<ProcessManager> = spin::Once::new();
 
-pub fn init(init: Arc<Process>, app_list: AppListRef) {
+pub fn init(init: Arc<Process>, app_list: AppListRef, scheduler: Box<dyn Scheduler>) {
     // DONE: set init process as Running
     init.write().resume();
     // DONE: set processor's current pid to init's pid
     processor::set_pid(init.pid());
 
-    PROCESS_MANAGER.call_once(|| ProcessManager::new(init, app_list));
+    PROCESS_MANAGER.call_once(|| ProcessManager::new(init, app_list, scheduler));
 }
 
 pub fn get_process_manager() -> &'static ProcessManager {
@@ -26,15 +29,60 @@ pub fn get_process_manager() -> &'static ProcessManager {
         .expect("Process Manager has not been initialized")
 }
 
+/// Processes that receive SIGINT on Ctrl-C
+///
+/// a foreground process that spawns hands the console over to its
+/// children, e.g. every stage of a pipeline, and gets it back once
+/// the last of them exits
+#[derive(Debug)]
+struct Foreground {
+    /// where the console returns to
+    owner: ProcessId,
+    group: BTreeSet<ProcessId>,
+}
+
+impl Foreground {
+    fn new(pid: ProcessId) -> Self {
+        Self {
+            owner: pid,
+            group: BTreeSet::from([pid]),
+        }
+    }
+
+    fn spawned(&mut self, pid: ProcessId, parent: ProcessId) {
+        if self.group.contains(&parent) {
+            self.owner = parent;
+            self.group = BTreeSet::from([pid]);
+        } else if self.owner == parent {
+            self.group.insert(pid);
+        }
+    }
+
+    fn exited(&mut self, pid: ProcessId, parent: Option<ProcessId>) {
+        if self.owner == pid {
+            self.owner = parent.unwrap_or(KERNEL_PID);
+        }
+        if self.group.remove(&pid) && self.group.is_empty() {
+            self.group.insert(self.owner);
+        }
+    }
+}
+
 pub struct ProcessManager {
     processes: RwLock<BTreeMap<ProcessId, Arc<Process>>>,
-    ready_queue: Mutex<VecDeque<ProcessId>>,
-    wait_queue: Mutex<BTreeMap<ProcessId, BTreeSet<ProcessId>>>,
+    scheduler: Mutex<Box<dyn Scheduler>>,
+    /// parents blocked until one of their children exits
+    wait_queue: Mutex<BTreeSet<ProcessId>>,
+    /// processes blocked on a resource, by its wait channel
+    io_queue: Mutex<BTreeMap<usize, VecDeque<ProcessId>>>,
+    /// sleeping processes ordered by their deadline in monotonic nanoseconds
+    sleep_queue: Mutex<BinaryHeap<Reverse<(u64, ProcessId)>>>,
+    foreground: Mutex<Foreground>,
     app_list: AppListRef,
 }
 
 impl ProcessManager {
-    pub fn new(init: Arc<Process>, app_list: AppListRef) -> Self {
+    pub fn new(init: Arc<Process>, app_list: AppListRef, scheduler: Box<dyn Scheduler>) -> Self {
         let mut processes = BTreeMap::new();
         let pid = init.pid();
 
@@ -43,15 +91,18 @@ impl ProcessManager {
         processes.insert(pid, init);
         Self {
             processes: RwLock::new(processes),
-            ready_queue: Mutex::new(VecDeque::new()),
-            wait_queue: Mutex::new(BTreeMap::new()),
+            scheduler: Mutex::new(scheduler),
+            wait_queue: Mutex::new(BTreeSet::new()),
+            io_queue: Mutex::new(BTreeMap::new()),
+            sleep_queue: Mutex::new(BinaryHeap::new()),
+            foreground: Mutex::new(Foreground::new(pid)),
             app_list,
         }
     }
 
     #[inline]
     pub fn push_ready(&self, pid: ProcessId) {
-        self.ready_queue.lock().push_back(pid);
+        self.scheduler.lock().push(pid);
     }
 
     #[inline]
@@ -81,13 +132,56 @@ impl ProcessManager {
         self.current().read().write(fd, buf)
     }
 
-    pub fn open(&self, path: &str) -> Option<u8> {
-        let stream = match get_rootfs().open_file(path) {
-            Ok(file) => Resource::File(file),
-            Err(_) => return None,
+    #[inline]
+    pub fn ioctl(&self, fd: u8, request: usize, termios: &mut Termios) -> isize {
+        self.current().read().ioctl(fd, request, termios)
+    }
+
+    pub fn pipe(&self, flags: usize) -> Option<(u8, u8)> {
+        self.current().write().pipe(flags & O_CLOEXEC != 0)
+    }
+
+    pub fn dup(&self, fd: u8) -> Option<u8> {
+        self.current().write().dup(fd)
+    }
+
+    pub fn dup2(&self, fd: u8, new_fd: u8) -> Option<u8> {
+        self.current().write().dup2(fd, new_fd)
+    }
+
+    pub fn open(&self, path: &str, flags: usize) -> Option<u8> {
+        let nonblocking = flags & O_NONBLOCK != 0;
+        let stream = match path {
+            "/dev/stdin" => Resource::Console(StdIO::Stdin),
+            path => match crate::filesystem::open_fifo(path) {
+                Some(pipe) => {
+                    let end = match flags & O_ACCMODE {
+                        O_RDONLY => PipeEnd::reader(pipe),
+                        O_WRONLY => PipeEnd::writer(pipe),
+                        _ => return None,
+                    };
+                    // there is nobody to take the data
+                    if nonblocking && end.is_writer() && !end.has_peer() {
+                        return None;
+                    }
+                    Resource::Pipe(end)
+                }
+                None => match get_rootfs().open_file(path) {
+                    Ok(file) => Resource::File(file),
+                    Err(_) => return None,
+                },
+            },
         };
 
-        let fd = self.current().write().open(stream);
+        let proc = self.current();
+        let mut inner = proc.write();
+        let fd = inner.open(stream)?;
+        if nonblocking {
+            inner.set_nonblocking(fd, true);
+        }
+        if flags & O_CLOEXEC != 0 {
+            inner.set_cloexec(fd, true);
+        }
         Some(fd)
     }
     pub fn close(&self, fd: u8) -> bool {
@@ -125,6 +219,13 @@ impl ProcessManager {
         trace!("New {:#?}", &proc);
 
         let pid = proc.pid();
+        if let Some(parent) = proc.read().parent() {
+            parent.write().add_child(proc.clone());
+
+            // a program started from the foreground takes over the console
+            self.foreground.lock().spawned(pid, parent.pid());
+        }
+
         // DONE: something like kernel thread
         self.add_proc(pid, proc);
         self.push_ready(pid);
@@ -132,13 +233,32 @@ impl ProcessManager {
         pid
     }
 
+    /// Account a timer tick of `elapsed_ns` to the current process
+    ///
+    /// returns whether the scheduler wants to switch to another process
+    pub fn tick(&self, elapsed_ns: u64) -> bool {
+        let pid = processor::get_pid();
+        let Some(proc) = self.get_proc(&pid) else {
+            return true;
+        };
+
+        let mut inner = proc.write();
+        // DONE: update current process's tick count
+        inner.tick(elapsed_ns);
+        let running = inner.status() == ProgramStatus::Running;
+        drop(inner);
+
+        self.scheduler.lock().tick(pid, elapsed_ns) || !running
+    }
+
     pub fn save_current(&self, context: &ProcessContext) {
-        let proc = self.current();
+        // the current process may have been killed and reaped already
+        let Some(proc) = self.get_proc(&processor::get_pid()) else {
+            return;
+        };
         let pid = proc.pid();
 
         let mut inner = proc.write();
-        // DONE: update current process's tick count
-        inner.tick();
         // DONE: save current process's context
         inner.save(context);
 
@@ -157,8 +277,10 @@ impl ProcessManager {
         let mut next_pid = processor::get_pid();
 
         // DONE: check if the next process is ready, continue to fetch if not ready
-        while let Some(pid) = self.ready_queue.lock().pop_front() {
-            let proc = self.get_proc(&pid).unwrap();
+        while let Some(pid) = self.scheduler.lock().pop() {
+            let Some(proc) = self.get_proc(&pid) else {
+                continue;
+            };
 
             if proc.read().is_ready() {
                 next_pid = pid;
@@ -166,6 +288,11 @@ impl ProcessManager {
             }
         }
 
+        // fall back to the kernel process if the current one has been reaped
+        if self.get_proc(&next_pid).is_none() {
+            next_pid = KERNEL_PID;
+        }
+
         // DONE: restore next process's context
         let next_proc = self.get_proc(&next_pid).unwrap();
         next_proc.write().restore(context);
@@ -177,7 +304,7 @@ impl ProcessManager {
         next_pid
     }
 
-    pub fn kill(&self, pid: ProcessId, ret: isize) {
+    pub fn kill(&self, pid: ProcessId, status: ExitStatus) {
         let Some(proc) = self.get_proc(&pid) else {
             error!("Process #{} not found.", pid);
             return;
@@ -188,20 +315,260 @@ impl ProcessManager {
             return;
         }
 
-        if let Some(pids) = self.wait_queue.lock().remove(&pid) {
-            for pid in pids {
-                self.wake_up(pid, Some(ret));
+        // closing its fds wakes up whoever waits on them, but not itself
+        self.remove_io_waiter(pid);
+        SEMAPHORES.lock().release(pid);
+        let owners = MUTEXES.lock().release(pid, self);
+        for owner in owners {
+            self.wake_up(owner, Some(0));
+        }
+        // the page tables of the current process are freed with it,
+        // the kernel's are loaded until the next switch instead
+        if pid == processor::get_pid() && pid != KERNEL_PID {
+            let kproc = self.get_proc(&KERNEL_PID).unwrap();
+            kproc.read().vm().page_table.load();
+        }
+        proc.kill(status);
+        self.adopt_orphans(&proc);
+
+        // the kernel process never waits, so its children are reaped on exit
+        let parent = proc.read().parent().map(|p| p.pid());
+
+        self.foreground.lock().exited(pid, parent);
+        match parent {
+            Some(ppid) if ppid != KERNEL_PID => {
+                // the parent restarts its wait and collects the zombie
+                if self.wait_queue.lock().remove(&ppid) {
+                    self.wake_up(ppid, None);
+                }
+            }
+            _ => self.reap(pid),
+        }
+    }
+    pub fn kill_current(&self, status: ExitStatus) {
+        self.kill(processor::get_pid(), status);
+    }
+
+    pub fn set_priority(&self, pid: ProcessId, priority: usize) -> Option<usize> {
+        let proc = self.get_proc(&pid)?;
+        if proc.read().is_dead() {
+            return None;
+        }
+
+        self.scheduler.lock().set_priority(pid, priority)
+    }
+
+    pub fn priority(&self, pid: ProcessId) -> usize {
+        self.scheduler.lock().priority(pid)
+    }
+
+    /// Send a signal to a process, signal 0 only checks that it exists
+    ///
+    /// the signal is delivered when the process returns to user mode,
+    /// unless its default action kills it, which happens right away
+    pub fn send_signal(&self, pid: ProcessId, signal: usize) -> bool {
+        if pid == KERNEL_PID {
+            return false;
+        }
+
+        let Some(proc) = self.get_proc(&pid) else {
+            return false;
+        };
+
+        let mut inner = proc.write();
+        if inner.is_dead() {
+            return false;
+        }
+        if signal == 0 {
+            return true;
+        }
+
+        trace!("Sending signal {} to #{}", signal, pid);
+
+        inner.signal_mut().raise(signal);
+        let terminate = inner.signal().terminates(signal);
+        drop(inner);
+
+        // a blocked process would not return to user mode for a while,
+        // the current one will be killed on its way out of the kernel
+        if terminate && pid != processor::get_pid() {
+            self.kill(pid, ExitStatus::signaled(signal));
+        } else {
+            self.interrupt_wait(pid);
+        }
+
+        true
+    }
+
+    /// Wake up a process blocked in a syscall that is restarted after waking up
+    ///
+    /// so that a pending signal is handled without waiting for the event
+    ///
+    /// sleeps and timed semaphore waits end early, as if the time was up
+    fn interrupt_wait(&self, pid: ProcessId) {
+        let sleeping = self.cancel_sleep(pid);
+        if sleeping {
+            SEMAPHORES.lock().cancel_wait(pid);
+        }
+
+        let waiting =
+            sleeping || self.wait_queue.lock().remove(&pid) || self.remove_io_waiter(pid);
+
+        if waiting {
+            self.wake_up(pid, None);
+        }
+    }
+
+    pub fn interrupt_foreground(&self) {
+        let group = self.foreground.lock().group.clone();
+        for pid in group {
+            if pid != KERNEL_PID {
+                self.send_signal(pid, SIGINT);
+            }
+        }
+    }
+
+    /// Deliver a pending signal before returning to user mode
+    ///
+    /// if the process is killed by it, switch to the next one and retry
+    pub fn handle_signals(&self, context: &mut ProcessContext) {
+        while context.is_user() {
+            let proc = self.current();
+            let mut inner = proc.write();
+
+            let Some(signal) = inner.signal_mut().take_pending() else {
+                return;
+            };
+
+            let action = inner.signal().action(signal);
+            let blocked = inner.signal().blocked();
+
+            match action.handler {
+                SIG_IGN => continue,
+                SIG_DFL if default_ignored(signal) => continue,
+                SIG_DFL => {}
+                _ => {
+                    // make sure the frame does not fault in the middle of being written
+                    let bottom =
+                        signal::frame_bottom(context).and_then(|b| VirtAddr::try_new(b).ok());
+                    if let Some(bottom) = bottom
+                        && !is_user_accessable(bottom.as_u64() as usize)
+                    {
+                        inner.handle_page_fault(bottom);
+                    }
+                    drop(inner);
+
+                    if bottom.is_some() && signal::setup_frame(context, signal, action, blocked) {
+                        // the signal is blocked while its handler runs
+                        proc.write()
+                            .signal_mut()
+                            .set_blocked(blocked | mask(signal));
+                        return;
+                    }
+
+                    warn!("Failed to deliver signal {} to #{}", signal, proc.pid());
+                    self.kill(proc.pid(), ExitStatus::signaled(SIGSEGV));
+                    self.switch_next(context);
+                    continue;
+                }
+            }
+
+            drop(inner);
+            self.kill(proc.pid(), ExitStatus::signaled(signal));
+            self.switch_next(context);
+        }
+    }
+
+    /// Return from a signal handler
+    pub fn sigreturn(&self, context: &mut ProcessContext) {
+        let proc = self.current();
+        match signal::restore_frame(context) {
+            Some(blocked) => proc.write().signal_mut().set_blocked(blocked),
+            None => {
+                warn!("Invalid signal frame for #{}", proc.pid());
+                self.kill(proc.pid(), ExitStatus::signaled(SIGSEGV));
+                self.switch_next(context);
             }
         }
+    }
+
+    /// Hand the children of a dead process over to the kernel process
+    ///
+    /// children that are already zombies are reaped right away
+    fn adopt_orphans(&self, proc: &Arc<Process>) {
+        let children = proc.write().take_children();
+        if children.is_empty() {
+            return;
+        }
+
+        let init = self.get_proc(&KERNEL_PID).unwrap();
+        for child in children {
+            child.write().set_parent(Arc::downgrade(&init));
+            let is_zombie = child.read().is_dead();
+            init.write().add_child(child.clone());
 
-        proc.kill(ret);
+            if is_zombie {
+                self.reap(child.pid());
+            }
+        }
     }
-    pub fn kill_current(&self, ret: isize) {
-        self.kill(processor::get_pid(), ret);
+
+    /// Reap a zombie child of the current process
+    ///
+    /// `None` matches any child, returns `Err` if no child matches
+    /// and `Ok(None)` if the matching children are all still running
+    pub fn collect(
+        &self,
+        target: Option<ProcessId>,
+    ) -> Result<Option<(ProcessId, ExitStatus)>, ()> {
+        let current = self.current();
+        let inner = current.read();
+
+        let mut found = false;
+        let mut zombie = None;
+        for child in inner
+            .children()
+            .iter()
+            .filter(|c| target.is_none_or(|pid| c.pid() == pid))
+        {
+            found = true;
+            if let Some(status) = child.read().exit_status() {
+                zombie = Some((child.pid(), status));
+                break;
+            }
+        }
+        drop(inner);
+
+        if !found {
+            return Err(());
+        }
+
+        if let Some((pid, _)) = zombie {
+            self.reap(pid);
+        }
+
+        Ok(zombie)
     }
 
-    pub fn get_exit_code(&self, pid: ProcessId) -> Option<isize> {
-        self.get_proc(&pid).and_then(|p| p.read().exit_code())
+    /// Remove a dead process from the process table and release its pid
+    fn reap(&self, pid: ProcessId) {
+        let Some(proc) = self.processes.write().remove(&pid) else {
+            return;
+        };
+
+        if let Some(parent) = proc.read().parent() {
+            parent.write().remove_child(pid);
+        }
+
+        // make sure nothing refers to the pid before it gets reused
+        self.scheduler.lock().remove(pid);
+        self.wait_queue.lock().remove(&pid);
+        self.remove_io_waiter(pid);
+        self.cancel_sleep(pid);
+
+        pid.free();
+
+        trace!("Reaped process #{}", pid);
     }
 
     pub fn handle_page_fault(&self, addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
@@ -234,45 +601,45 @@ impl ProcessManager {
             info!("Page Fault on kernel at {:#x}", addr);
         }
 
-        proc.write().handle_page_fault(addr);
-
-        true
+        proc.write().handle_page_fault(addr)
     }
 
     pub fn print_process_list(&self) {
-        let mut output = String::from("  PID | PPID | Process Name |  Ticks  |  Memory  | Status\n");
+        let mut output = String::from("  PID | PPID | Process Name | CPU (ms) |  Memory  | Status\n");
 
         self.processes
             .read()
             .values()
-            .filter(|p| p.read().status() != ProgramStatus::Dead)
             .for_each(|p| output += format!("{p}\n").as_str());
 
-        // TODO: print memory usage of kernel heap
+        output += &crate::memory::allocator::ALLOCATOR.usage();
 
         let alloc = get_frame_alloc_for_sure();
-        let frames_used = alloc.frames_used();
-        let frames_recycled = alloc.frames_recycled();
-        let frames_total = alloc.frames_total();
-
-        let used = (frames_used - frames_recycled) * PAGE_SIZE as usize;
-        let total = frames_total * PAGE_SIZE as usize;
+        let used = alloc.frames_used() * PAGE_SIZE as usize;
+        let total = alloc.frames_total() * PAGE_SIZE as usize;
 
         output += &format_usage("Memory", used, total);
+
+        // free blocks of every order, from 4 KiB up
+        output += "Free   :";
+        for (order, count) in alloc.free_blocks().iter().enumerate() {
+            let (size, unit) = humanized_size_short(PAGE_SIZE << order);
+            output += format!(" {}{}x{}", size, unit, count).as_str();
+        }
+        output += "\n";
         drop(alloc);
 
-        output += format!("Queue  : {:?}\n", self.ready_queue.lock()).as_str();
+        output += format!("Sched  : {:?}\n", self.scheduler.lock()).as_str();
 
         output += &processor::print_processors();
 
         print!("{}", output);
     }
 
-    pub fn wait_pid(&self, pid: ProcessId) {
-        let mut wait_queue = self.wait_queue.lock();
+    /// Wait for any child of the current process to exit
+    pub fn wait_child(&self) {
         // DONE: push the current process to the wait queue
-        let entry = wait_queue.entry(pid).or_default();
-        entry.insert(processor::get_pid());
+        self.wait_queue.lock().insert(processor::get_pid());
     }
 
     pub fn fork(&self) {
@@ -285,7 +652,115 @@ impl ProcessManager {
         self.push_ready(pid);
 
         // FOR DBG: maybe print the process ready queue?
-        debug!("Ready Queue: {:?}", self.ready_queue.lock());
+        debug!("Scheduler: {:?}", self.scheduler.lock());
+    }
+
+    /// Block the current process until the other side of `fd` is opened
+    pub fn wait_open(&self, fd: u8) {
+        if let Some(channel) = self.current().read().open_channel(fd) {
+            self.wait_channel(channel);
+        }
+    }
+
+    /// Block the current process until the resource behind `fd` is ready
+    pub fn wait_resource(&self, fd: u8) {
+        if let Some(channel) = self.current().read().wait_channel(fd) {
+            self.wait_channel(channel);
+        }
+    }
+
+    /// Block the current process until the channel is woken up
+    pub fn wait_channel(&self, channel: usize) {
+        let pid = processor::get_pid();
+        self.io_queue.lock().entry(channel).or_default().push_back(pid);
+        self.block(pid);
+    }
+
+    /// Wake up every process waiting on the channel
+    ///
+    /// they restart their syscalls, those that still cannot go on block again
+    pub fn wake_channel(&self, channel: usize) {
+        let waiters = self.io_queue.lock().remove(&channel);
+        for pid in waiters.into_iter().flatten() {
+            self.wake_up(pid, None);
+        }
+    }
+
+    /// Wake up at most `count` processes waiting on the channel, in the order they came
+    ///
+    /// returns how many processes were woken up
+    pub fn wake_channel_n(&self, channel: usize, count: usize) -> usize {
+        let mut queue = self.io_queue.lock();
+        let Some(waiters) = queue.get_mut(&channel) else {
+            return 0;
+        };
+
+        let woken: Vec<ProcessId> = waiters.drain(..count.min(waiters.len())).collect();
+        if waiters.is_empty() {
+            queue.remove(&channel);
+        }
+        drop(queue);
+
+        for &pid in woken.iter() {
+            self.wake_up(pid, None);
+        }
+        woken.len()
+    }
+
+    /// Returns whether the process was waiting on any channel
+    fn remove_io_waiter(&self, pid: ProcessId) -> bool {
+        let mut queue = self.io_queue.lock();
+        let channel = queue
+            .iter()
+            .find(|(_, waiters)| waiters.contains(&pid))
+            .map(|(&channel, _)| channel);
+
+        let Some(channel) = channel else {
+            return false;
+        };
+
+        let waiters = queue.get_mut(&channel).unwrap();
+        waiters.retain(|&p| p != pid);
+        if waiters.is_empty() {
+            queue.remove(&channel);
+        }
+        true
+    }
+
+    /// Put the current process to sleep until the monotonic clock reaches `deadline`
+    pub fn sleep_until(&self, deadline: u64) {
+        let pid = processor::get_pid();
+        self.sleep_queue.lock().push(Reverse((deadline, pid)));
+        self.block(pid);
+    }
+
+    /// Wake up the sleepers whose time has come
+    pub fn wake_sleepers(&self, now: u64) {
+        loop {
+            let mut queue = self.sleep_queue.lock();
+            let Some(&Reverse((deadline, pid))) = queue.peek() else {
+                return;
+            };
+            if deadline > now {
+                return;
+            }
+            queue.pop();
+            drop(queue);
+
+            // a timed semaphore wait is over
+            SEMAPHORES.lock().cancel_wait(pid);
+            self.wake_up(pid, None);
+        }
+    }
+
+    /// Remove the process from the sleepers, it was woken up by something else
+    ///
+    /// returns whether it was sleeping
+    pub fn cancel_sleep(&self, pid: ProcessId) -> bool {
+        let mut queue = self.sleep_queue.lock();
+        let len = queue.len();
+        queue.retain(|Reverse((_, p))| *p != pid);
+        queue.len() != len
     }
 
     /// Block the process with the given pid
@@ -305,6 +780,9 @@ impl ProcessManager {
                 // DONE: set the return value of the process
                 inner.set_return(ret as usize);
             }
+            if inner.is_dead() {
+                return;
+            }
             // DONE: set the process as ready
             // DONE: push to ready queue
             inner.pause();
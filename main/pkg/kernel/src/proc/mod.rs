@@ -1,31 +1,46 @@
 mod context;
 mod data;
 mod manager;
+mod mq;
+mod mutex;
 mod paging;
 mod pid;
 mod process;
 mod processor;
+mod sched;
+pub mod signal;
 mod sync;
 mod vm;
 
 use boot::BootInfo;
 use manager::*;
+use mq::*;
+use mutex::*;
 use process::*;
+use sched::*;
+use signal::*;
 use storage::*;
 use sync::*;
 use vm::*;
 
-use crate::Resource;
 use crate::filesystem::get_rootfs;
+use crate::pipe::PipeEnd;
+use crate::{Resource, WOULD_BLOCK};
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::sync::Arc;
+use core::alloc::Layout;
+use core::sync::atomic::Ordering;
 use xmas_elf::ElfFile;
-pub use context::ProcessContext;
+pub use context::{ProcessContext, ProcessContextValue};
 pub use data::ProcessData;
 pub use paging::PageTableContext;
 pub use pid::ProcessId;
+pub use syscall_def::wait::ExitStatus;
+use syscall_def::fs::*;
+use syscall_def::tty::Termios;
 
+use crate::interrupt::clock;
 use x86_64::VirtAddr;
 use x86_64::structures::idt::PageFaultErrorCode;
 pub const KERNEL_PID: ProcessId = ProcessId(1);
@@ -47,7 +62,14 @@ pub fn init(boot_info: &'static BootInfo) {
     // kernel process
     let kproc = Process::new(String::from("kernel"), None, Some(proc_vm), None);
     let app_list = boot_info.loaded_apps.as_ref();
-    manager::init(kproc, app_list);
+    let scheduler = new_scheduler(boot_info.cmdline_option("sched"));
+    debug!("Scheduler: {:?}", scheduler);
+    // warn about semaphore deadlocks, or fail the wait that closes one
+    SEMAPHORES
+        .lock()
+        .set_fail_deadlocks(boot_info.cmdline_option("deadlock") == Some("error"));
+    ALLOW_WX.store(boot_info.cmdline_option("wx") == Some("allow"), Ordering::Relaxed);
+    manager::init(kproc, app_list, scheduler);
 
     info!("Process Manager Initialized.");
 }
@@ -59,31 +81,44 @@ pub fn switch(context: &mut ProcessContext) {
         //   - handle ready queue update
         //   - restore next process's context
         let manager = get_process_manager();
+        if !manager.tick(clock::tick_ns()) {
+            return;
+        }
         manager.save_current(context);
         //manager.push_ready(processor::get_pid());
         manager.switch_next(context);
     });
 }
 
-pub fn spawn(name: &str) -> Option<ProcessId> {
+/// Spawn an app, it inherits the fds of the current process
+/// with stdin and stdout taken from `stdio`
+pub fn spawn(name: &str, stdio: (u8, u8)) -> Option<ProcessId> {
     let app = x86_64::instructions::interrupts::without_interrupts(|| {
         let app_list = get_process_manager().app_list()?;
         app_list.iter().find(|&app| app.name.eq(name))
     })?;
 
-    elf_spawn(name.to_string(), &app.elf)
+    elf_spawn(name.to_string(), &app.elf, stdio)
 }
 
-pub fn elf_spawn(name: String, elf: &ElfFile) -> Option<ProcessId> {
+pub fn elf_spawn(name: String, elf: &ElfFile, stdio: (u8, u8)) -> Option<ProcessId> {
+    // W^X: no page of a program is both writable and executable
+    if !is_wx_allowed() && has_wx_segment(elf) {
+        warn!("Refused to spawn {}: segment is writable and executable.", name);
+        return None;
+    }
+
     let pid = x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let process_name = name.to_lowercase();
-        let parent = Arc::downgrade(&manager.current());
-        let pid = manager.spawn(elf, name, Some(parent), None);
+        let current = manager.current();
+        let proc_data = current.read().inherit(stdio)?;
+        let parent = Arc::downgrade(&current);
+        let pid = manager.spawn(elf, name, Some(parent), Some(proc_data));
 
         debug!("Spawned process: {}#{}", process_name, pid);
-        pid
-    });
+        Some(pid)
+    })?;
 
     Some(pid)
 }
@@ -109,40 +144,207 @@ pub fn env(key: &str) -> Option<String> {
     })
 }
 
-pub fn read(fd: u8, buf: &mut [u8]) -> isize {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().read(fd, buf))
+/// Read from a resource, blocking if there is no data yet
+///
+/// a blocked read is restarted once data arrives
+pub fn read(fd: u8, buf: &mut [u8], context: &mut ProcessContext) -> Option<isize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let ret = manager.read(fd, buf);
+        if ret != WOULD_BLOCK {
+            context.set_rax(ret as usize);
+            return Some(ret);
+        }
+
+        context.restart_syscall();
+        manager.save_current(context);
+        manager.wait_resource(fd);
+        manager.switch_next(context);
+        None
+    })
+}
+/// Write to a resource, blocking if there is no space yet
+///
+/// a blocked write is restarted once the other side reads
+pub fn write(fd: u8, buf: &[u8], context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let ret = manager.write(fd, buf);
+        if ret != WOULD_BLOCK {
+            context.set_rax(ret as usize);
+            return;
+        }
+
+        context.restart_syscall();
+        manager.save_current(context);
+        manager.wait_resource(fd);
+        manager.switch_next(context);
+    })
+}
+pub fn pipe(flags: usize) -> Option<(u8, u8)> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().pipe(flags))
+}
+/// Terminal request on `fd`, `termios` is copied from and to the user by the caller
+pub fn ioctl(fd: u8, request: usize, termios: &mut Termios) -> isize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().ioctl(fd, request, termios)
+    })
+}
+
+/// Open a file or a FIFO, sets the fd or `0` as the return value
+///
+/// opening a FIFO blocks until the other end is opened as well,
+/// the process wakes up with the fd already set
+pub fn open(path: &str, flags: usize, context: &mut ProcessContext) -> Option<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let fd = manager.open(path, flags);
+        context.set_rax(fd.unwrap_or(0) as usize);
+
+        if let Some(fd) = fd
+            && manager.current().read().open_channel(fd).is_some()
+        {
+            manager.save_current(context);
+            manager.wait_open(fd);
+            manager.switch_next(context);
+        }
+        fd
+    })
 }
-pub fn write(fd: u8, buf: &[u8]) -> isize {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().write(fd, buf))
+pub fn dup(fd: u8) -> Option<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().dup(fd))
 }
-pub fn open(path: &str) -> Option<u8> {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().open(path))
+pub fn dup2(fd: u8, new_fd: u8) -> Option<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().dup2(fd, new_fd))
+}
+pub fn mkfifo(path: &str) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| crate::filesystem::mkfifo(path))
 }
 pub fn close(fd: u8) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().close(fd))
 }
 
 pub fn still_alive(pid: ProcessId) -> bool {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().get_exit_code(pid).is_none())
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .get_proc(&pid)
+            .is_some_and(|p| !p.read().is_dead())
+    })
 }
 
-pub fn wait_pid(pid: ProcessId, context: &mut ProcessContext) {
+/// Collect a zombie child, `None` waits for any child
+///
+/// with `nohang` set, returns `0` instead of blocking; when blocking,
+/// the syscall is restarted after a child exits and collects it then
+pub fn wait_pid(
+    target: Option<ProcessId>,
+    nohang: bool,
+    context: &mut ProcessContext,
+) -> Option<(ProcessId, ExitStatus)> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        if let Some(ret) = manager.get_exit_code(pid) {
-            context.set_rax(ret as usize);
-        } else {
-            manager.wait_pid(pid);
-            manager.save_current(context);
-            manager.current().write().block();
-            manager.switch_next(context);
+        match manager.collect(target) {
+            Ok(Some(child)) => return Some(child),
+            // no such child, or it has already been reaped
+            Err(()) => context.set_rax(usize::MAX),
+            Ok(None) if nohang => context.set_rax(0),
+            Ok(None) => {
+                context.restart_syscall();
+                manager.save_current(context);
+                manager.wait_child();
+                manager.current().write().block();
+                manager.switch_next(context);
+            }
+        }
+        None
+    })
+}
+
+/// Give up the cpu, the process stays ready
+pub fn yield_now(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        context.set_rax(0);
+        manager.save_current(context);
+        manager.switch_next(context);
+    })
+}
+
+/// Block the current process for at least `ms` milliseconds
+pub fn sleep(ms: u64, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        context.set_rax(0);
+        manager.save_current(context);
+        if ms > 0 {
+            let deadline = clock::monotonic_ns().saturating_add(ms.saturating_mul(1_000_000));
+            manager.sleep_until(deadline);
         }
+        manager.switch_next(context);
+    })
+}
+
+/// Wake up the processes blocked on a resource
+pub fn wake_channel(channel: usize) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().wake_channel(channel);
+    })
+}
+
+/// Block until `futex_wake` is called on `addr`, unless the word there
+/// no longer holds `expected`
+///
+/// sets `0` as the return value, a waiter may also be woken up by a signal
+pub fn futex_wait(addr: usize, expected: u32, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let Some(channel) = futex_channel(addr) else {
+            return context.set_rax(usize::MAX);
+        };
+        let Some(value) = crate::memory::UserPtr::<u32>::new(addr).read() else {
+            return context.set_rax(usize::MAX);
+        };
+        context.set_rax(0);
+
+        if value != expected {
+            return;
+        }
+
+        let manager = get_process_manager();
+        manager.save_current(context);
+        manager.wait_channel(channel);
+        manager.switch_next(context);
+    })
+}
+
+/// Wake up at most `count` processes waiting on `addr`
+pub fn futex_wake(addr: usize, count: usize) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let channel = futex_channel(addr)?;
+        Some(get_process_manager().wake_channel_n(channel, count))
+    })
+}
+
+/// Futexes are keyed by the physical address of the word, so that processes
+/// sharing memory meet on the same one, the kernel alias of the word is used
+/// as it cannot clash with the heap objects used as channels
+fn futex_channel(addr: usize) -> Option<usize> {
+    if !addr.is_multiple_of(4) {
+        return None;
+    }
+    let phys = crate::memory::user_to_physical(addr)?;
+    Some(crate::memory::physical_to_virtual(phys) as usize)
+}
+
+pub fn wake_sleepers(now: u64) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().wake_sleepers(now);
     })
 }
 
+/// Create or open a semaphore
 pub fn new_sem(key: u32, value: usize) -> usize {
     x86_64::instructions::interrupts::without_interrupts(|| {
-        if get_process_manager().current().write().new_sem(key, value) {
+        if SEMAPHORES.lock().insert(key, value, processor::get_pid()) {
             0
         } else {
             1
@@ -151,53 +353,274 @@ pub fn new_sem(key: u32, value: usize) -> usize {
 }
 pub fn remove_sem(key: u32) -> usize {
     x86_64::instructions::interrupts::without_interrupts(|| {
-        if get_process_manager().current().write().remove_sem(key) {
-            0
-        } else {
-            1
+        let Some(waiters) = SEMAPHORES.lock().remove(key, processor::get_pid()) else {
+            return 1;
+        };
+
+        // the waiters find the semaphore gone
+        let manager = get_process_manager();
+        for pid in waiters {
+            manager.cancel_sleep(pid);
+            manager.wake_up(pid, Some(1));
         }
+        0
     })
 }
 pub fn sem_signal(key: u32, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        let ret = manager.current().write().sem_signal(key);
+        let ret = SEMAPHORES.lock().signal(key, processor::get_pid());
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
             SemaphoreResult::NotExist => context.set_rax(1),
-            SemaphoreResult::WakeUp(pid) => manager.wake_up(pid, None),
+            SemaphoreResult::WakeUp(pid) => {
+                context.set_rax(0);
+                // it may be in a timed wait
+                manager.cancel_sleep(pid);
+                manager.wake_up(pid, Some(0));
+            }
             _ => unreachable!(),
         }
     })
 }
-pub fn sem_wait(key: u32, context: &mut ProcessContext) {
+/// Wait the semaphore, a wait with a `timeout` in milliseconds
+/// returns `2` if the time is up before it is signaled
+///
+/// returns `3` if waiting would deadlock and the kernel is set to fail such waits
+pub fn sem_wait(key: u32, timeout: Option<u64>, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let pid = processor::get_pid();
-        let ret = manager.current().write().sem_wait(key, pid);
+        let ret = SEMAPHORES.lock().wait(key, pid);
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
             SemaphoreResult::NotExist => context.set_rax(1),
+            SemaphoreResult::Deadlock => context.set_rax(3),
             SemaphoreResult::Block(pid) => {
                 // DONE: save, block it, then switch to next
+                // the return value is set by whoever signals
+                context.set_rax(2);
                 manager.save_current(context);
-                manager.block(pid);
+                match timeout {
+                    Some(ms) => {
+                        let timeout = ms.saturating_mul(1_000_000);
+                        manager.sleep_until(clock::monotonic_ns().saturating_add(timeout))
+                    }
+                    None => manager.block(pid),
+                }
                 manager.switch_next(context);
             }
             _ => unreachable!(),
         }
     })
 }
+/// Returns `2` instead of blocking
+pub fn sem_try_wait(key: u32) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        match SEMAPHORES.lock().try_wait(key, processor::get_pid()) {
+            SemaphoreResult::Ok => 0,
+            SemaphoreResult::NotExist => 1,
+            SemaphoreResult::Busy => 2,
+            _ => unreachable!(),
+        }
+    })
+}
+/// Print which processes hold and wait for each semaphore
+pub fn print_semaphores() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        print!("{}", SEMAPHORES.lock().wait_for_graph());
+    })
+}
+pub fn sem_value(key: u32) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        SEMAPHORES.lock().value(key, processor::get_pid())
+    })
+}
+
+/// Create or open a mutex
+pub fn new_mutex(key: u32) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        MUTEXES.lock().insert(key, processor::get_pid());
+        0
+    })
+}
+pub fn remove_mutex(key: u32) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let Some(waiters) = MUTEXES.lock().remove(key, manager) else {
+            return 1;
+        };
+
+        // the waiters find the mutex gone
+        for pid in waiters {
+            manager.wake_up(pid, Some(1));
+        }
+        0
+    })
+}
+/// Lock a mutex, the owner inherits the priority of the process while it waits
+///
+/// returns `3` if the process already owns the mutex
+pub fn mutex_lock(key: u32, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let pid = processor::get_pid();
+        let ret = MUTEXES.lock().lock(key, pid, manager);
+        match ret {
+            MutexResult::Ok => context.set_rax(0),
+            MutexResult::NotExist => context.set_rax(1),
+            MutexResult::Deadlock => context.set_rax(3),
+            MutexResult::Block => {
+                // the mutex is handed over by the owner when it unlocks
+                manager.save_current(context);
+                manager.block(pid);
+                manager.switch_next(context);
+            }
+        }
+    })
+}
+/// Unlock a mutex owned by the current process, returns `1` otherwise
+pub fn mutex_unlock(key: u32) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let ret = MUTEXES.lock().unlock(key, processor::get_pid(), manager);
+        match ret {
+            Ok(next) => {
+                if let Some(next) = next {
+                    manager.wake_up(next, Some(0));
+                }
+                0
+            }
+            Err(()) => 1,
+        }
+    })
+}
+
+pub fn mq_open(key: u32, capacity: usize, msg_size: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .write()
+            .mq_open(key, capacity, msg_size)
+    })
+}
+
+pub fn mq_remove(key: u32) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().write().mq_remove(key)
+    })
+}
+
+/// Send a message, blocking while the queue is full
+pub fn mq_send(key: u32, priority: usize, buf: &[u8], context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let ret = get_process_manager().current().read().mq_send(key, priority, buf);
+        mq_result(ret, context);
+    })
+}
+
+/// Receive the message with the highest priority, blocking while the queue is empty
+///
+/// returns the length of the message once one is received
+pub fn mq_recv(key: u32, buf: &mut [u8], context: &mut ProcessContext) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let ret = get_process_manager().current().read().mq_recv(key, buf);
+        mq_result(ret, context)
+    })
+}
+
+/// A blocked send or receive is restarted once the queue changes
+fn mq_result(ret: MessageQueueResult, context: &mut ProcessContext) -> Option<usize> {
+    match ret {
+        MessageQueueResult::Ok(ret) => {
+            context.set_rax(ret);
+            return Some(ret);
+        }
+        MessageQueueResult::NotExist | MessageQueueResult::Invalid => {
+            context.set_rax(usize::MAX)
+        }
+        MessageQueueResult::Block(channel) => {
+            let manager = get_process_manager();
+            context.restart_syscall();
+            manager.save_current(context);
+            manager.wait_channel(channel);
+            manager.switch_next(context);
+        }
+    }
+    None
+}
 
 pub fn process_exit(ret: isize, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         // DONE: implement this for ProcessManager
-        manager.kill_current(ret);
+        manager.kill_current(ExitStatus::exited(ret));
         manager.switch_next(context);
     })
 }
 
+pub fn kill(pid: ProcessId, signal: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().send_signal(pid, signal)
+    })
+}
+
+/// Set the priority of a process, `None` for the current one
+pub fn set_priority(pid: Option<ProcessId>, priority: usize) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let pid = pid.unwrap_or_else(processor::get_pid);
+        MUTEXES.lock().set_priority(pid, priority, get_process_manager())
+    })
+}
+
+/// Kill the current process without switching away from it
+pub fn kill_current(status: ExitStatus) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().kill_current(status);
+    })
+}
+
+/// Send SIGINT to the foreground processes
+pub fn interrupt_foreground() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().interrupt_foreground();
+    })
+}
+
+pub fn handle_signals(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().handle_signals(context);
+    })
+}
+
+pub fn sigreturn(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().sigreturn(context);
+    })
+}
+
+pub fn sigaction(signal: usize, handler: usize, restorer: usize) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let action = SignalAction { handler, restorer };
+        let old = get_process_manager()
+            .current()
+            .write()
+            .signal_mut()
+            .sigaction(signal, action)?;
+        Some(old.handler)
+    })
+}
+
+pub fn sigprocmask(how: usize, set: u64) -> Option<u64> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .write()
+            .signal_mut()
+            .sigprocmask(how, set)
+    })
+}
+
 pub fn handle_page_fault(addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
         get_process_manager().handle_page_fault(addr, err_code)
@@ -238,6 +661,54 @@ pub fn fork(context: &mut ProcessContext) {
     })
 }
 
+pub fn shm_create(key: u32, size: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().shm_create(key, size)
+    })
+}
+
+pub fn shm_attach(key: u32, addr: Option<VirtAddr>) -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().shm_attach(key, addr)
+    })
+}
+
+pub fn shm_detach(addr: VirtAddr) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().shm_detach(addr)
+    })
+}
+
+pub fn mmap(addr: Option<VirtAddr>, len: u64, prot: usize, flags: usize) -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().mmap(addr, len, prot, flags)
+    })
+}
+
+pub fn munmap(addr: VirtAddr, len: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().munmap(addr, len)
+    })
+}
+
+pub fn mprotect(addr: VirtAddr, len: u64, prot: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().mprotect(addr, len, prot)
+    })
+}
+
+pub fn allocate(layout: Layout) -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().allocate(layout)
+    })
+}
+
+pub fn deallocate(addr: VirtAddr, layout: Layout) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().deallocate(addr, layout)
+    })
+}
+
 pub fn brk(addr: Option<VirtAddr>) -> Option<VirtAddr> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // NOTE: `brk` does not need to get write lock
@@ -0,0 +1,144 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+pub use syscall_def::mq::*;
+
+#[derive(Debug)]
+struct Message {
+    priority: usize,
+    data: Vec<u8>,
+}
+
+/// Bounded queue of messages, higher priorities are received first
+/// and messages of the same priority in the order they were sent
+#[derive(Debug)]
+pub struct MessageQueue {
+    capacity: usize,
+    msg_size: usize,
+    /// one queue per priority, the last one has the highest priority
+    messages: [Vec<Message>; MQ_PRIO_MAX],
+    len: usize,
+}
+
+/// Message queue result
+#[derive(Debug)]
+pub enum MessageQueueResult {
+    /// size of the received message, `0` for a sent one
+    Ok(usize),
+    NotExist,
+    Invalid,
+    /// wait on the channel until the queue changes
+    Block(usize),
+}
+
+impl MessageQueue {
+    pub fn new(capacity: usize, msg_size: usize) -> Self {
+        Self {
+            capacity,
+            msg_size,
+            messages: core::array::from_fn(|_| Vec::new()),
+            len: 0,
+        }
+    }
+
+    fn send(&mut self, priority: usize, data: &[u8]) -> Option<bool> {
+        if data.len() > self.msg_size || priority >= MQ_PRIO_MAX {
+            return None;
+        }
+        if self.len == self.capacity {
+            return Some(false);
+        }
+
+        self.messages[priority].push(Message {
+            priority,
+            data: data.to_vec(),
+        });
+        self.len += 1;
+        Some(true)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Option<Option<usize>> {
+        if buf.len() < self.msg_size {
+            return None;
+        }
+
+        let Some(queue) = self.messages.iter_mut().rev().find(|q| !q.is_empty()) else {
+            return Some(None);
+        };
+
+        let msg = queue.remove(0);
+        trace!("MQ Recv: priority {}, {} bytes", msg.priority, msg.data.len());
+        buf[..msg.data.len()].copy_from_slice(&msg.data);
+        self.len -= 1;
+        Some(Some(msg.data.len()))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MessageQueueSet {
+    queues: BTreeMap<u32, Arc<Mutex<MessageQueue>>>,
+}
+
+impl MessageQueueSet {
+    /// Create the queue unless it exists, the sizes have to be valid either way
+    pub fn open(&mut self, key: u32, capacity: usize, msg_size: usize) -> bool {
+        trace!("MQ Open: <{:#x}> {} x {} bytes", key, capacity, msg_size);
+
+        if !(1..=MQ_MAX_MSGS).contains(&capacity) || !(1..=MQ_MAX_MSG_SIZE).contains(&msg_size) {
+            return false;
+        }
+
+        self.queues
+            .entry(key)
+            .or_insert_with(|| Arc::new(Mutex::new(MessageQueue::new(capacity, msg_size))));
+        true
+    }
+
+    /// Remove the queue, processes blocked on it fail when they retry
+    pub fn remove(&mut self, key: u32) -> bool {
+        trace!("MQ Remove: <{:#x}>", key);
+
+        let Some(queue) = self.queues.remove(&key) else {
+            return false;
+        };
+        crate::proc::wake_channel(channel(&queue));
+        true
+    }
+
+    pub fn send(&self, key: u32, priority: usize, data: &[u8]) -> MessageQueueResult {
+        let Some(queue) = self.queues.get(&key) else {
+            return MessageQueueResult::NotExist;
+        };
+
+        let sent = queue.lock().send(priority, data);
+        match sent {
+            None => MessageQueueResult::Invalid,
+            Some(false) => MessageQueueResult::Block(channel(queue)),
+            Some(true) => {
+                crate::proc::wake_channel(channel(queue));
+                MessageQueueResult::Ok(0)
+            }
+        }
+    }
+
+    pub fn recv(&self, key: u32, buf: &mut [u8]) -> MessageQueueResult {
+        let Some(queue) = self.queues.get(&key) else {
+            return MessageQueueResult::NotExist;
+        };
+
+        let received = queue.lock().recv(buf);
+        match received {
+            None => MessageQueueResult::Invalid,
+            Some(None) => MessageQueueResult::Block(channel(queue)),
+            Some(Some(len)) => {
+                crate::proc::wake_channel(channel(queue));
+                MessageQueueResult::Ok(len)
+            }
+        }
+    }
+}
+
+/// Senders and receivers blocked on the queue wait on this channel
+fn channel(queue: &Arc<Mutex<MessageQueue>>) -> usize {
+    Arc::as_ptr(queue) as usize
+}
@@ -0,0 +1,235 @@
+use super::{ProcessId, ProcessManager};
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Mutexes are shared by every process, like semaphores
+pub static MUTEXES: Mutex<MutexSet> = Mutex::new(MutexSet::new());
+
+/// Longest chain of owners waiting on each other that gets boosted
+const MAX_INHERIT_DEPTH: usize = 16;
+
+/// A lock with an owner, the owner runs with the priority
+/// of its most urgent waiter until it unlocks
+#[derive(Debug, Default)]
+struct KernelMutex {
+    owner: Option<ProcessId>,
+    wait_queue: VecDeque<ProcessId>,
+    /// processes that used the mutex, it is dropped once they all exit
+    users: BTreeSet<ProcessId>,
+}
+
+#[derive(Debug)]
+pub enum MutexResult {
+    Ok,
+    NotExist,
+    /// the process already owns the mutex
+    Deadlock,
+    Block,
+}
+
+#[derive(Debug, Default)]
+pub struct MutexSet {
+    mutexes: BTreeMap<u32, KernelMutex>,
+    /// priorities of the boosted processes before they inherited one
+    base: BTreeMap<ProcessId, usize>,
+}
+
+impl MutexSet {
+    pub const fn new() -> Self {
+        Self {
+            mutexes: BTreeMap::new(),
+            base: BTreeMap::new(),
+        }
+    }
+
+    /// Look up the mutex, `pid` becomes one of its users
+    fn get(&mut self, key: u32, pid: ProcessId) -> Option<&mut KernelMutex> {
+        let mutex = self.mutexes.get_mut(&key)?;
+        mutex.users.insert(pid);
+        Some(mutex)
+    }
+
+    /// Create the mutex unlocked, or open it if it exists
+    pub fn insert(&mut self, key: u32, pid: ProcessId) {
+        trace!("Mutex Insert: <{:#x}>", key);
+
+        if self.get(key, pid).is_none() {
+            let mut mutex = KernelMutex::default();
+            mutex.users.insert(pid);
+            self.mutexes.insert(key, mutex);
+        }
+    }
+
+    /// Remove the mutex, returns the processes that were waiting on it
+    pub fn remove(&mut self, key: u32, manager: &ProcessManager) -> Option<VecDeque<ProcessId>> {
+        trace!("Mutex Remove: <{:#x}>", key);
+
+        let mutex = self.mutexes.remove(&key)?;
+        if let Some(owner) = mutex.owner {
+            self.update_priority(owner, manager);
+        }
+        Some(mutex.wait_queue)
+    }
+
+    /// Take the mutex, or queue up and lend the priority to the owner
+    pub fn lock(&mut self, key: u32, pid: ProcessId, manager: &ProcessManager) -> MutexResult {
+        let Some(mutex) = self.get(key, pid) else {
+            return MutexResult::NotExist;
+        };
+
+        let owner = match mutex.owner {
+            None => {
+                mutex.owner = Some(pid);
+                return MutexResult::Ok;
+            }
+            Some(owner) if owner == pid => return MutexResult::Deadlock,
+            Some(owner) => owner,
+        };
+
+        mutex.wait_queue.push_back(pid);
+        trace!("Mutex Lock: <{:#x}> #{} waits for #{}", key, pid, owner);
+
+        // an owner blocked on another mutex passes the priority on
+        let mut owner = Some(owner);
+        for _ in 0..MAX_INHERIT_DEPTH {
+            let Some(pid) = owner else {
+                break;
+            };
+            self.update_priority(pid, manager);
+            owner = self.waiting_for(pid);
+        }
+
+        MutexResult::Block
+    }
+
+    /// Release the mutex, returns the waiter it was handed over to
+    ///
+    /// fails with `Err` if `pid` does not own the mutex
+    pub fn unlock(
+        &mut self,
+        key: u32,
+        pid: ProcessId,
+        manager: &ProcessManager,
+    ) -> Result<Option<ProcessId>, ()> {
+        let mutex = self.get(key, pid).ok_or(())?;
+        if mutex.owner != Some(pid) {
+            return Err(());
+        }
+
+        // the most urgent waiter goes first, the earliest one among equals
+        let next = mutex
+            .wait_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|&(i, &waiter)| (manager.priority(waiter), i))
+            .map(|(i, _)| i)
+            .and_then(|i| mutex.wait_queue.remove(i));
+        mutex.owner = next;
+
+        self.update_priority(pid, manager);
+        if let Some(next) = next {
+            // the new owner inherits from the ones still waiting
+            self.update_priority(next, manager);
+        }
+        Ok(next)
+    }
+
+    /// Forget an exiting process, the mutexes it owns are handed over
+    ///
+    /// returns the processes that now own a mutex
+    pub fn release(&mut self, pid: ProcessId, manager: &ProcessManager) -> Vec<ProcessId> {
+        let owned: Vec<u32> = self
+            .mutexes
+            .iter()
+            .filter(|(_, mutex)| mutex.owner == Some(pid))
+            .map(|(&key, _)| key)
+            .collect();
+
+        let woken = owned
+            .into_iter()
+            .filter_map(|key| self.unlock(key, pid, manager).ok().flatten())
+            .collect();
+
+        // the owner it was waiting for may not need a boost anymore
+        let owner = self.waiting_for(pid);
+        for mutex in self.mutexes.values_mut() {
+            mutex.wait_queue.retain(|&p| p != pid);
+        }
+        if let Some(owner) = owner {
+            self.update_priority(owner, manager);
+        }
+
+        self.base.remove(&pid);
+        self.mutexes.retain(|key, mutex| {
+            mutex.users.remove(&pid);
+            if mutex.users.is_empty() {
+                trace!("Mutex Drop: <{:#x}>", key);
+            }
+            !mutex.users.is_empty()
+        });
+
+        woken
+    }
+
+    /// Set the priority of a process on request, returns the old one
+    ///
+    /// a boosted process keeps the inherited priority while it is higher,
+    /// and gets the new one back on unlock
+    pub fn set_priority(
+        &mut self,
+        pid: ProcessId,
+        priority: usize,
+        manager: &ProcessManager,
+    ) -> Option<usize> {
+        let Some(&base) = self.base.get(&pid) else {
+            return manager.set_priority(pid, priority);
+        };
+
+        manager.set_priority(pid, priority)?;
+        self.base.insert(pid, priority);
+        self.update_priority(pid, manager);
+        Some(base)
+    }
+
+    /// The owner of the mutex `pid` waits for
+    fn waiting_for(&self, pid: ProcessId) -> Option<ProcessId> {
+        self.mutexes
+            .values()
+            .find(|mutex| mutex.wait_queue.contains(&pid))
+            .and_then(|mutex| mutex.owner)
+    }
+
+    /// Give the process the priority of its most urgent waiter,
+    /// or its own priority back if that is higher
+    fn update_priority(&mut self, pid: ProcessId, manager: &ProcessManager) {
+        let base = match self.base.get(&pid) {
+            Some(&base) => base,
+            None => manager.priority(pid),
+        };
+
+        let inherited = self
+            .mutexes
+            .values()
+            .filter(|mutex| mutex.owner == Some(pid))
+            .flat_map(|mutex| mutex.wait_queue.iter())
+            .map(|&waiter| manager.priority(waiter))
+            .min();
+
+        match inherited {
+            Some(priority) if priority < base => {
+                self.base.insert(pid, base);
+                if manager.set_priority(pid, priority).is_some() {
+                    debug!("Mutex: #{} inherits priority {} (was {})", pid, priority, base);
+                }
+            }
+            _ => {
+                if self.base.remove(&pid).is_some() {
+                    debug!("Mutex: #{} gets its priority {} back", pid, base);
+                    manager.set_priority(pid, base);
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,50 @@
-use core::sync::atomic::{AtomicU16, Ordering};
+use bit_field::BitField;
+use spin::Mutex;
+
+/// Number of distinct pids, pid 0 means "no process"
+const PID_COUNT: usize = u16::MAX as usize + 1;
+
+static PID_BITMAP: Mutex<PidBitmap> = Mutex::new(PidBitmap::new());
+
+/// Bitmap of the pids in use
+///
+/// pids are handed out round-robin from `next`, so a released pid
+/// is not reused until the whole pid space has been walked through
+struct PidBitmap {
+    bits: [u64; PID_COUNT / 64],
+    next: usize,
+}
+
+impl PidBitmap {
+    const fn new() -> Self {
+        let mut bits = [0; PID_COUNT / 64];
+        // pid 0 is reserved for idle processors
+        bits[0] = 1;
+        Self { bits, next: 1 }
+    }
+
+    fn is_used(&self, pid: usize) -> bool {
+        self.bits[pid / 64].get_bit(pid % 64)
+    }
+
+    fn alloc(&mut self) -> Option<u16> {
+        for offset in 0..PID_COUNT {
+            let pid = (self.next + offset) % PID_COUNT;
+            if !self.is_used(pid) {
+                self.bits[pid / 64].set_bit(pid % 64, true);
+                self.next = (pid + 1) % PID_COUNT;
+                return Some(pid as u16);
+            }
+        }
+        None
+    }
+
+    fn free(&mut self, pid: u16) {
+        let pid = pid as usize;
+        debug_assert!(self.is_used(pid), "Freeing unused pid {}", pid);
+        self.bits[pid / 64].set_bit(pid % 64, false);
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ProcessId(pub u16);
@@ -6,8 +52,15 @@ pub struct ProcessId(pub u16);
 impl ProcessId {
     pub fn new() -> Self {
         // DONE: Get a unique PID
-        static NEXT_PID: AtomicU16 = AtomicU16::new(1);
-        ProcessId(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+        let pid = PID_BITMAP.lock().alloc().expect("No available pid");
+        ProcessId(pid)
+    }
+
+    /// Release the pid so that it can be handed out again
+    ///
+    /// NOTE: only call this after the process has been reaped
+    pub fn free(self) {
+        PID_BITMAP.lock().free(self.0);
     }
 }
 
@@ -15,10 +15,12 @@ pub struct ProcessInner {
     name: String,
     parent: Option<Weak<Process>>,
     children: Vec<Arc<Process>>,
-    ticks_passed: usize,
+    /// time spent running, in nanoseconds
+    cpu_time: u64,
     status: ProgramStatus,
     context: ProcessContext,
-    exit_code: Option<isize>,
+    exit_status: Option<ExitStatus>,
+    signal: SignalState,
     proc_data: Option<ProcessData>,
     proc_vm: Option<ProcessVm>,
 }
@@ -56,8 +58,9 @@ impl Process {
             parent,
             status: ProgramStatus::Ready,
             context: ProcessContext::default(),
-            ticks_passed: 0,
-            exit_code: None,
+            cpu_time: 0,
+            exit_status: None,
+            signal: SignalState::default(),
             children: Vec::new(),
             proc_vm: Some(proc_vm),
             proc_data: Some(proc_data.unwrap_or_default()),
@@ -72,17 +75,17 @@ impl Process {
         })
     }
 
-    pub fn kill(&self, ret: isize) {
+    pub fn kill(&self, status: ExitStatus) {
         let mut inner = self.inner.write();
 
         debug!(
-            "Killing process {}#{} with ret code: {}",
+            "Killing process {}#{} with {}",
             inner.name(),
             self.pid,
-            ret
+            status
         );
 
-        inner.kill(self.pid, ret);
+        inner.kill(status);
     }
 
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
@@ -116,8 +119,8 @@ impl ProcessInner {
         &self.name
     }
 
-    pub fn tick(&mut self) {
-        self.ticks_passed += 1;
+    pub fn tick(&mut self, elapsed_ns: u64) {
+        self.cpu_time += elapsed_ns;
     }
 
     pub fn status(&self) -> ProgramStatus {
@@ -133,33 +136,27 @@ impl ProcessInner {
     pub fn block(&mut self) {
         self.status = ProgramStatus::Blocked;
     }
-    pub fn kill(&mut self, pid: ProcessId, ret: isize) {
-        let children = self.children();
-
-        // remove self from parent, and set parent to children
-        if let Some(parent) = self.parent() {
-            if parent.read().exit_code().is_none() {
-                parent.write().remove_child(pid);
-                let weak = Arc::downgrade(&parent);
-                for child in children {
-                    child.write().set_parent(weak.clone());
-                }
-            } else {
-                // parent already exited, set parent to None
-                for child in children {
-                    child.write().set_parent(Weak::new());
-                }
-            }
-        }
-
+    /// Release the process's resources and turn it into a zombie
+    ///
+    /// the entry is kept until the parent collects the exit code,
+    /// children are left for the process manager to reparent
+    pub fn kill(&mut self, status: ExitStatus) {
         self.proc_vm.take();
         self.proc_data.take();
-        self.exit_code = Some(ret);
+        self.exit_status = Some(status);
         self.status = ProgramStatus::Dead;
     }
 
-    pub fn exit_code(&self) -> Option<isize> {
-        self.exit_code
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
+
+    pub fn signal(&self) -> &SignalState {
+        &self.signal
+    }
+
+    pub fn signal_mut(&mut self) -> &mut SignalState {
+        &mut self.signal
     }
 
     pub fn clone_page_table(&self) -> PageTableContext {
@@ -236,11 +233,52 @@ impl ProcessInner {
     pub fn remove_child(&mut self, child: ProcessId) {
         self.children.retain(|c| c.pid() != child);
     }
+    pub fn take_children(&mut self) -> Vec<Arc<Process>> {
+        core::mem::take(&mut self.children)
+    }
 
     pub fn brk(&self, addr: Option<VirtAddr>) -> Option<VirtAddr> {
         self.vm().brk(addr)
     }
 
+    pub fn mmap(
+        &self,
+        addr: Option<VirtAddr>,
+        len: u64,
+        prot: usize,
+        flags: usize,
+    ) -> Option<VirtAddr> {
+        self.vm().mmap(addr, len, prot, flags)
+    }
+
+    pub fn munmap(&self, addr: VirtAddr, len: u64) -> bool {
+        self.vm().munmap(addr, len)
+    }
+
+    pub fn mprotect(&self, addr: VirtAddr, len: u64, prot: usize) -> bool {
+        self.vm().mprotect(addr, len, prot)
+    }
+
+    pub fn allocate(&self, layout: Layout) -> Option<VirtAddr> {
+        self.vm().allocate(layout)
+    }
+
+    pub fn deallocate(&self, addr: VirtAddr, layout: Layout) -> bool {
+        self.vm().deallocate(addr, layout)
+    }
+
+    pub fn shm_create(&self, key: u32, size: u64) -> bool {
+        self.vm().shm_create(key, size)
+    }
+
+    pub fn shm_attach(&self, key: u32, addr: Option<VirtAddr>) -> Option<VirtAddr> {
+        self.vm().shm_attach(key, addr)
+    }
+
+    pub fn shm_detach(&self, addr: VirtAddr) -> bool {
+        self.vm().shm_detach(addr)
+    }
+
     pub fn fork(&mut self, parent: Weak<Process>) -> ProcessInner {
         // DONE: fork the process virtual memory struct
         // DONE: calculate the real stack offset
@@ -257,10 +295,11 @@ impl ProcessInner {
         // DONE: construct the child process inner
         Self {
             name: self.name.clone(),
-            exit_code: None,
+            exit_status: None,
+            signal: self.signal.fork(),
             parent: Some(parent),
             status: ProgramStatus::Ready,
-            ticks_passed: 0,
+            cpu_time: 0,
             context: new_context,
             children: Vec::new(),
             proc_vm: Some(new_vm),
@@ -305,7 +344,7 @@ impl core::fmt::Debug for Process {
             .field("name", &inner.name)
             .field("parent", &inner.parent().map(|p| p.pid))
             .field("status", &inner.status)
-            .field("ticks_passed", &inner.ticks_passed)
+            .field("cpu_time", &inner.cpu_time)
             .field("children", &inner.children.iter().map(|c| c.pid.0))
             .field("status", &inner.status)
             .field("context", &inner.context)
@@ -320,11 +359,11 @@ impl core::fmt::Display for Process {
         let (size, unit) = humanized_size(inner.proc_vm.as_ref().map_or(0, |vm| vm.memory_usage()));
         write!(
             f,
-            " #{:-3} | #{:-3} | {:12} | {:7} | {:>5.1}{} | {:?}",
+            " #{:-3} | #{:-3} | {:12} | {:8} | {:>5.1}{} | {:?}",
             self.pid.0,
             inner.parent().map(|p| p.pid.0).unwrap_or(0),
             inner.name,
-            inner.ticks_passed,
+            inner.cpu_time / 1_000_000,
             size,
             unit,
             inner.status
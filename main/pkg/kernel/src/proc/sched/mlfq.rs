@@ -0,0 +1,168 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+use super::*;
+use crate::proc::KERNEL_PID;
+
+/// Number of priority levels, 0 is the highest
+pub const LEVELS: usize = 4;
+
+/// Time a process may run at each level before it is demoted
+const TIME_SLICE_NS: [u64; LEVELS] = [
+    10 * NANOS_PER_MS,
+    20 * NANOS_PER_MS,
+    40 * NANOS_PER_MS,
+    80 * NANOS_PER_MS,
+];
+
+/// Time between two boosts of every process to its top level
+const BOOST_INTERVAL_NS: u64 = 2000 * NANOS_PER_MS;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Task {
+    /// current level
+    level: usize,
+    /// highest level the task can reach, set by `set_priority`
+    priority: usize,
+    /// cpu time used at the current level
+    used: u64,
+    queued: bool,
+}
+
+/// Multilevel feedback queue
+///
+/// - new processes start at their top level
+/// - a process that uses up the time slice of its level is demoted,
+///   giving up the cpu early does not reset the used time
+/// - a process is preempted as soon as a higher level has work
+/// - all processes are moved back to their top level periodically
+///
+/// the kernel process only idles, so it stays at the lowest level
+#[derive(Debug)]
+pub struct Mlfq {
+    queues: [VecDeque<ProcessId>; LEVELS],
+    tasks: BTreeMap<ProcessId, Task>,
+    /// cpu time since the last boost
+    since_boost: u64,
+}
+
+impl Mlfq {
+    pub fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            tasks: BTreeMap::new(),
+            since_boost: 0,
+        }
+    }
+
+    fn task(&mut self, pid: ProcessId) -> &mut Task {
+        self.tasks.entry(pid).or_insert_with(|| {
+            let level = if pid == KERNEL_PID { LEVELS - 1 } else { 0 };
+            Task {
+                level,
+                priority: level,
+                ..Task::default()
+            }
+        })
+    }
+
+    fn boost(&mut self) {
+        trace!("MLFQ: boosting all processes");
+
+        for task in self.tasks.values_mut() {
+            task.level = task.priority;
+            task.used = 0;
+        }
+
+        let queued: Vec<ProcessId> = self.queues.iter_mut().flat_map(|q| q.drain(..)).collect();
+        for pid in queued {
+            let level = self.tasks[&pid].level;
+            self.queues[level].push_back(pid);
+        }
+    }
+
+    fn has_work_above(&self, level: usize) -> bool {
+        self.queues[..level].iter().any(|q| !q.is_empty())
+    }
+}
+
+impl Default for Mlfq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for Mlfq {
+    fn push(&mut self, pid: ProcessId) {
+        let task = self.task(pid);
+        if task.queued {
+            return;
+        }
+
+        if task.used >= TIME_SLICE_NS[task.level] {
+            task.level = (task.level + 1).min(LEVELS - 1);
+            task.used = 0;
+        }
+        task.queued = true;
+
+        let level = task.level;
+        self.queues[level].push_back(pid);
+    }
+
+    fn pop(&mut self) -> Option<ProcessId> {
+        let pid = self.queues.iter_mut().find_map(|q| q.pop_front())?;
+        self.task(pid).queued = false;
+        Some(pid)
+    }
+
+    fn remove(&mut self, pid: ProcessId) {
+        if let Some(task) = self.tasks.remove(&pid) {
+            self.queues[task.level].retain(|&p| p != pid);
+        }
+    }
+
+    fn tick(&mut self, pid: ProcessId, elapsed_ns: u64) -> bool {
+        self.since_boost += elapsed_ns;
+        if self.since_boost >= BOOST_INTERVAL_NS {
+            self.since_boost = 0;
+            self.boost();
+        }
+
+        let task = self.task(pid);
+        task.used += elapsed_ns;
+
+        let level = task.level;
+        task.used >= TIME_SLICE_NS[level] || self.has_work_above(level)
+    }
+
+    fn set_priority(&mut self, pid: ProcessId, priority: usize) -> Option<usize> {
+        if priority >= LEVELS {
+            return None;
+        }
+
+        let task = self.task(pid);
+        let old = task.priority;
+        task.priority = priority;
+
+        // takes effect right away, a queued task moves to its new level
+        let level = task.level;
+        let queued = task.queued;
+        task.level = priority;
+        task.used = 0;
+
+        if queued && level != priority {
+            self.queues[level].retain(|&p| p != pid);
+            self.queues[priority].push_back(pid);
+        }
+
+        Some(old)
+    }
+
+    fn priority(&self, pid: ProcessId) -> usize {
+        match self.tasks.get(&pid) {
+            Some(task) => task.priority,
+            None if pid == KERNEL_PID => LEVELS - 1,
+            None => 0,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use alloc::boxed::Box;
+
+use super::ProcessId;
+
+const NANOS_PER_MS: u64 = 1_000_000;
+
+pub mod mlfq;
+pub mod round_robin;
+
+use self::{mlfq::Mlfq, round_robin::RoundRobin};
+
+/// Scheduling policy of the process manager
+///
+/// only ready processes are pushed, but a process may have been blocked
+/// or killed by the time it is popped, the caller skips those
+pub trait Scheduler: Send + core::fmt::Debug {
+    /// Make a process runnable, pushing a queued process is a no-op
+    fn push(&mut self, pid: ProcessId);
+
+    /// Take the next process to run
+    fn pop(&mut self) -> Option<ProcessId>;
+
+    /// Forget everything about a reaped process
+    fn remove(&mut self, pid: ProcessId);
+
+    /// Account `elapsed_ns` of cpu time to the running process
+    ///
+    /// returns whether it should be preempted
+    fn tick(&mut self, pid: ProcessId, elapsed_ns: u64) -> bool;
+
+    /// Set the priority of a process, 0 is the highest,
+    /// returns the old one
+    fn set_priority(&mut self, pid: ProcessId, priority: usize) -> Option<usize>;
+
+    /// The priority of a process, 0 is the highest
+    fn priority(&self, pid: ProcessId) -> usize;
+}
+
+/// Select the scheduler by name, as given by `sched=` on the kernel cmdline
+pub fn new_scheduler(name: Option<&str>) -> Box<dyn Scheduler> {
+    match name {
+        Some("mlfq") => Box::new(Mlfq::new()),
+        Some("rr") | None => Box::new(RoundRobin::new()),
+        Some(name) => {
+            warn!("Unknown scheduler '{}', using round robin.", name);
+            Box::new(RoundRobin::new())
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use alloc::collections::VecDeque;
+
+use super::*;
+
+/// Time a process may run before the next one gets the cpu
+const QUANTUM_NS: u64 = 10 * NANOS_PER_MS;
+
+/// Switch to the next process once the quantum is used up
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    queue: VecDeque<ProcessId>,
+    /// the running process and the time it used so far
+    running: Option<(ProcessId, u64)>,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn push(&mut self, pid: ProcessId) {
+        if !self.queue.contains(&pid) {
+            self.queue.push_back(pid);
+        }
+    }
+
+    fn pop(&mut self) -> Option<ProcessId> {
+        self.queue.pop_front()
+    }
+
+    fn remove(&mut self, pid: ProcessId) {
+        self.queue.retain(|&p| p != pid);
+    }
+
+    fn tick(&mut self, pid: ProcessId, elapsed_ns: u64) -> bool {
+        let used = match self.running {
+            Some((running, used)) if running == pid => used + elapsed_ns,
+            _ => elapsed_ns,
+        };
+
+        if used >= QUANTUM_NS {
+            self.running = None;
+            true
+        } else {
+            self.running = Some((pid, used));
+            false
+        }
+    }
+
+    fn set_priority(&mut self, _pid: ProcessId, _priority: usize) -> Option<usize> {
+        // every process is equal, priorities cannot be set
+        None
+    }
+
+    fn priority(&self, _pid: ProcessId) -> usize {
+        0
+    }
+}
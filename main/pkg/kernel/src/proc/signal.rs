@@ -0,0 +1,185 @@
+use super::*;
+use crate::memory::*;
+
+pub use syscall_def::signal::*;
+
+/// Bytes below the user stack pointer that leaf functions may use
+const RED_ZONE: u64 = 128;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalAction {
+    pub handler: usize,
+    /// where the handler returns to, it must issue `Sigreturn`
+    pub restorer: usize,
+}
+
+/// Saved on the user stack while a signal handler runs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFrame {
+    pub context: ProcessContextValue,
+    pub blocked: u64,
+}
+
+// the saved context is checked by `restore_user`
+unsafe impl UserData for SignalFrame {}
+
+#[derive(Debug, Clone)]
+pub struct SignalState {
+    pending: u64,
+    blocked: u64,
+    actions: [SignalAction; NSIG],
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [SignalAction::default(); NSIG],
+        }
+    }
+}
+
+impl SignalState {
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, blocked: u64) {
+        self.blocked = blocked & !UNCATCHABLE;
+    }
+
+    /// Update the blocked mask, returns the old mask
+    pub fn sigprocmask(&mut self, how: usize, set: u64) -> Option<u64> {
+        let old = self.blocked;
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return None,
+        };
+        self.set_blocked(blocked);
+        Some(old)
+    }
+
+    pub fn action(&self, signal: usize) -> SignalAction {
+        self.actions[signal]
+    }
+
+    /// Install a new action, returns the old one
+    pub fn sigaction(&mut self, signal: usize, action: SignalAction) -> Option<SignalAction> {
+        if !is_valid(signal) || UNCATCHABLE & mask(signal) != 0 {
+            return None;
+        }
+        if !matches!(action.handler, SIG_DFL | SIG_IGN) && action.restorer == 0 {
+            return None;
+        }
+
+        let old = core::mem::replace(&mut self.actions[signal], action);
+        // an ignored signal is discarded, even if it is already pending
+        if self.is_ignored(signal) {
+            self.pending &= !mask(signal);
+        }
+        Some(old)
+    }
+
+    fn is_ignored(&self, signal: usize) -> bool {
+        match self.actions[signal].handler {
+            SIG_IGN => true,
+            SIG_DFL => default_ignored(signal),
+            _ => false,
+        }
+    }
+
+    /// Whether delivering the signal right now kills the process
+    pub fn terminates(&self, signal: usize) -> bool {
+        self.blocked & mask(signal) == 0
+            && self.actions[signal].handler == SIG_DFL
+            && !default_ignored(signal)
+    }
+
+    /// Mark the signal as pending unless it is ignored
+    pub fn raise(&mut self, signal: usize) {
+        if !self.is_ignored(signal) {
+            self.pending |= mask(signal);
+        }
+    }
+
+    /// Take the lowest pending signal that is not blocked
+    pub fn take_pending(&mut self) -> Option<usize> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+
+        let signal = deliverable.trailing_zeros() as usize;
+        self.pending &= !mask(signal);
+        Some(signal)
+    }
+
+    /// Handlers and the blocked mask are inherited, pending signals are not
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Push a signal frame onto the user stack and enter the handler
+///
+/// the caller has to make sure the stack is mapped down to the
+/// address returned by `frame_bottom`
+pub fn setup_frame(
+    context: &mut ProcessContext,
+    signal: usize,
+    action: SignalAction,
+    blocked: u64,
+) -> bool {
+    let Some(bottom) = frame_bottom(context) else {
+        return false;
+    };
+
+    let frame = SignalFrame {
+        context: **context,
+        blocked,
+    };
+
+    // the handler returns to the restorer with the frame on top of the stack
+    if !UserPtr::<u64>::new(bottom as usize).write(action.restorer as u64)
+        || !UserPtr::<SignalFrame>::new(bottom as usize + 8).write(frame)
+    {
+        return false;
+    }
+
+    context.enter_handler(
+        VirtAddr::new(action.handler as u64),
+        VirtAddr::new(bottom),
+        signal,
+    );
+
+    true
+}
+
+/// Lowest address of the frame for the current user stack,
+/// `None` if the stack pointer is too low to hold it
+pub fn frame_bottom(context: &ProcessContext) -> Option<u64> {
+    let sp = context.stack_frame.stack_pointer.as_u64();
+    let frame = sp.checked_sub(RED_ZONE + core::mem::size_of::<SignalFrame>() as u64)? & !0xf;
+    // as if the handler was called: the return address sits below the frame
+    frame.checked_sub(8)
+}
+
+/// Pop the signal frame left by `setup_frame`, returns the saved blocked mask
+///
+/// the frame is on top of the stack when the restorer issues `Sigreturn`
+pub fn restore_frame(context: &mut ProcessContext) -> Option<u64> {
+    let ptr = context.stack_frame.stack_pointer.as_u64() as usize;
+    let frame = UserPtr::<SignalFrame>::new(ptr).read()?;
+    if !context.restore_user(&frame.context) {
+        return None;
+    }
+
+    Some(frame.blocked)
+}
@@ -1,6 +1,9 @@
 use super::ProcessId;
 
-use alloc::collections::{ BTreeMap, VecDeque };
+use alloc::collections::{ BTreeMap, BTreeSet, VecDeque };
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use spin::mutex::Mutex;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -12,11 +15,19 @@ impl SemaphoreId {
     }
 }
 
-/// Mutex is required for Semaphore
+/// Semaphores are shared by every process, whoever knows the key can use them
+pub static SEMAPHORES: Mutex<SemaphoreSet> = Mutex::new(SemaphoreSet::new());
+
 #[derive(Debug, Clone)]
 pub struct Semaphore {
     count: usize,
     wait_queue: VecDeque<ProcessId>,
+    /// the creator, only it can remove the semaphore while it is alive
+    owner: Option<ProcessId>,
+    /// processes that used the semaphore, it is dropped once they all exit
+    users: BTreeSet<ProcessId>,
+    /// processes that acquired the semaphore and did not signal it yet
+    holders: BTreeMap<ProcessId, usize>,
 }
 
 /// Semaphore result
@@ -24,16 +35,37 @@ pub struct Semaphore {
 pub enum SemaphoreResult {
     Ok,
     NotExist,
+    /// the count is 0 and the process does not wait
+    Busy,
     Block(ProcessId),
     WakeUp(ProcessId),
+    /// blocking would never end, the process does not wait
+    Deadlock,
 }
 
 impl Semaphore {
     /// Create a new semaphore
-    pub fn new(value: usize) -> Self {
+    pub fn new(value: usize, owner: ProcessId) -> Self {
         Self {
             count: value,
             wait_queue: VecDeque::new(),
+            owner: Some(owner),
+            users: BTreeSet::from([owner]),
+            holders: BTreeMap::new(),
+        }
+    }
+
+    fn hold(&mut self, pid: ProcessId) {
+        *self.holders.entry(pid).or_default() += 1;
+    }
+
+    /// a semaphore may also be signaled by a process that does not hold it
+    fn unhold(&mut self, pid: ProcessId) {
+        if let Some(count) = self.holders.get_mut(&pid) {
+            *count -= 1;
+            if *count == 0 {
+                self.holders.remove(&pid);
+            }
         }
     }
 
@@ -49,6 +81,18 @@ impl Semaphore {
             SemaphoreResult::Block(pid)
         } else {
             self.count -= 1;
+            self.hold(pid);
+            SemaphoreResult::Ok
+        }
+    }
+
+    /// Decrease the count without waiting
+    pub fn try_wait(&mut self, pid: ProcessId) -> SemaphoreResult {
+        if self.count == 0 {
+            SemaphoreResult::Busy
+        } else {
+            self.count -= 1;
+            self.hold(pid);
             SemaphoreResult::Ok
         }
     }
@@ -57,71 +101,232 @@ impl Semaphore {
     ///
     /// if the wait queue is not empty, then pop a process from the wait queue
     /// else increase the count
-    pub fn signal(&mut self) -> SemaphoreResult {
+    pub fn signal(&mut self, pid: ProcessId) -> SemaphoreResult {
         // DONE: if the wait queue is not empty, pop a process from the wait queue, return WakeUp(pid)
         // DONE: else increase the count and return Ok
+        self.unhold(pid);
         if let Some(pid) = self.wait_queue.pop_front() {
+            // the count is handed over to the waiter
+            self.hold(pid);
             SemaphoreResult::WakeUp(pid)
         } else {
             self.count += 1;
             SemaphoreResult::Ok
         }
     }
+
+    pub fn value(&self) -> usize {
+        self.count
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct SemaphoreSet {
-    sems: BTreeMap<SemaphoreId, Mutex<Semaphore>>,
+    sems: BTreeMap<SemaphoreId, Semaphore>,
+    /// fail a wait that would deadlock instead of only warning about it
+    fail_deadlocks: bool,
 }
 
 impl SemaphoreSet {
-    pub fn insert(&mut self, key: u32, value: usize) -> bool {
+    pub const fn new() -> Self {
+        Self {
+            sems: BTreeMap::new(),
+            fail_deadlocks: false,
+        }
+    }
+
+    pub fn set_fail_deadlocks(&mut self, fail: bool) {
+        self.fail_deadlocks = fail;
+    }
+
+    /// Look up the semaphore, `pid` becomes one of its users
+    fn get(&mut self, key: u32, pid: ProcessId) -> Option<&mut Semaphore> {
+        let sem = self.sems.get_mut(&SemaphoreId::new(key))?;
+        sem.users.insert(pid);
+        Some(sem)
+    }
+
+    /// Create the semaphore, or open it with its current count if it exists
+    pub fn insert(&mut self, key: u32, value: usize, pid: ProcessId) -> bool {
         trace!("Sem Insert: <{:#x}>{}", key, value);
 
         // DONE: insert a new semaphore into the sems, use `insert(/* ... */).is_none()`
-        self.sems.insert(SemaphoreId::new(key), Mutex::new(Semaphore::new(value))).is_none()
+        if self.get(key, pid).is_none() {
+            self.sems.insert(SemaphoreId::new(key), Semaphore::new(value, pid));
+        }
+        true
     }
 
-    pub fn remove(&mut self, key: u32) -> bool {
+    /// Remove the semaphore, returns the processes that were waiting on it
+    ///
+    /// fails for anyone but the owner while the owner is alive
+    pub fn remove(&mut self, key: u32, pid: ProcessId) -> Option<VecDeque<ProcessId>> {
         trace!("Sem Remove: <{:#x}>", key);
 
         // DONE: remove the semaphore from the sems, use `remove(/* ... */).is_some()`
-        self.sems.remove(&SemaphoreId::new(key)).is_some()
+        let sid = SemaphoreId::new(key);
+        let owner = self.sems.get(&sid)?.owner;
+        if owner.is_some_and(|owner| owner != pid) {
+            return None;
+        }
+        self.sems.remove(&sid).map(|sem| sem.wait_queue)
     }
 
     /// Wait the semaphore (acquire/down/proberen)
-    pub fn wait(&self, key: u32, pid: ProcessId) -> SemaphoreResult {
-        let sid = SemaphoreId::new(key);
-
+    pub fn wait(&mut self, key: u32, pid: ProcessId) -> SemaphoreResult {
         // DONE: try get the semaphore from the sems, then do it's operation
         // DONE: return NotExist if the semaphore is not exist
-        if let Some(sem) = self.sems.get(&sid) {
-            let mut locked = sem.lock();
-            trace!("Sem Wait  : <{:#x}>{}", key, locked);
-            locked.wait(pid)
-        } else {
-            SemaphoreResult::NotExist
+        let Some(sem) = self.get(key, pid) else {
+            return SemaphoreResult::NotExist;
+        };
+        trace!("Sem Wait  : <{:#x}>{}", key, sem);
+
+        let ret = sem.wait(pid);
+        if !matches!(ret, SemaphoreResult::Block(_)) {
+            return ret;
         }
+
+        let deadlocked = self.deadlocked();
+        if !deadlocked.contains(&pid) {
+            return ret;
+        }
+
+        warn!(
+            "Deadlock: #{} waits for <{:#x}>, stuck processes: {}",
+            pid,
+            key,
+            format_pids(deadlocked.iter())
+        );
+        if !self.fail_deadlocks {
+            return ret;
+        }
+
+        self.cancel_wait(pid);
+        SemaphoreResult::Deadlock
     }
 
-    /// Signal the semaphore (release/up/verhogen)
-    pub fn signal(&self, key: u32) -> SemaphoreResult {
-        let sid = SemaphoreId::new(key);
+    pub fn try_wait(&mut self, key: u32, pid: ProcessId) -> SemaphoreResult {
+        match self.get(key, pid) {
+            Some(sem) => sem.try_wait(pid),
+            None => SemaphoreResult::NotExist,
+        }
+    }
 
+    /// Signal the semaphore (release/up/verhogen)
+    pub fn signal(&mut self, key: u32, pid: ProcessId) -> SemaphoreResult {
         // DONE: try get the semaphore from the sems, then do it's operation
         // DONE: return NotExist if the semaphore is not exist
-        if let Some(sem) = self.sems.get(&sid) {
-            let mut locked = sem.lock();
-            trace!("Sem Signal: <{:#x}>{}", key, locked);
-            locked.signal()
+        if let Some(sem) = self.get(key, pid) {
+            trace!("Sem Signal: <{:#x}>{}", key, sem);
+            sem.signal(pid)
         } else {
             SemaphoreResult::NotExist
         }
     }
+
+    pub fn value(&mut self, key: u32, pid: ProcessId) -> Option<usize> {
+        self.get(key, pid).map(|sem| sem.value())
+    }
+
+    /// Stop waiting on any semaphore, e.g. when a timed wait expires
+    pub fn cancel_wait(&mut self, pid: ProcessId) {
+        for sem in self.sems.values_mut() {
+            sem.wait_queue.retain(|&p| p != pid);
+        }
+    }
+
+    /// Forget an exiting process, semaphores without users left are dropped
+    pub fn release(&mut self, pid: ProcessId) {
+        self.sems.retain(|sid, sem| {
+            sem.wait_queue.retain(|&p| p != pid);
+            sem.users.remove(&pid);
+            sem.holders.remove(&pid);
+            if sem.owner == Some(pid) {
+                sem.owner = None;
+            }
+            if sem.users.is_empty() {
+                trace!("Sem Drop  : <{:#x}>", sid.0);
+            }
+            !sem.users.is_empty()
+        });
+    }
+
+    /// Processes that can never be woken up
+    ///
+    /// a waiter is stuck when every holder of its semaphore is stuck as well,
+    /// assuming that a semaphore is signaled by the processes holding it
+    fn deadlocked(&self) -> BTreeSet<ProcessId> {
+        // every waiter with the holders of what it waits for
+        let edges: Vec<(ProcessId, Vec<ProcessId>)> = self
+            .sems
+            .values()
+            .flat_map(|sem| {
+                sem.wait_queue
+                    .iter()
+                    .map(move |&waiter| (waiter, sem.holders.keys().copied().collect()))
+            })
+            .collect();
+
+        let mut stuck: BTreeSet<ProcessId> = edges
+            .iter()
+            .filter(|(_, holders)| !holders.is_empty())
+            .map(|&(waiter, _)| waiter)
+            .collect();
+
+        // drop the waiters that someone can still wake up, until nothing changes
+        loop {
+            let free: Vec<ProcessId> = edges
+                .iter()
+                .filter(|(waiter, holders)| {
+                    stuck.contains(waiter) && holders.iter().any(|h| !stuck.contains(h))
+                })
+                .map(|&(waiter, _)| waiter)
+                .collect();
+
+            if free.is_empty() {
+                return stuck;
+            }
+            for waiter in free {
+                stuck.remove(&waiter);
+            }
+        }
+    }
+
+    /// Describe who waits for whom, and the processes that are deadlocked
+    pub fn wait_for_graph(&self) -> String {
+        let mut output = String::from("  Sem        | Count | Holders -> Waiters\n");
+
+        for (sid, sem) in self.sems.iter() {
+            output += format!(
+                "  <{:#010x}> | {:5} | {} -> {}\n",
+                sid.0,
+                sem.count,
+                format_pids(sem.holders.keys()),
+                format_pids(sem.wait_queue.iter())
+            )
+            .as_str();
+        }
+
+        output += format!("Deadlocked: {}\n", format_pids(self.deadlocked().iter())).as_str();
+        output
+    }
+}
+
+fn format_pids<'a>(pids: impl Iterator<Item = &'a ProcessId>) -> String {
+    let pids: Vec<String> = pids.map(|pid| format!("#{}", pid)).collect();
+    if pids.is_empty() {
+        String::from("-")
+    } else {
+        pids.join(" ")
+    }
 }
 
 impl core::fmt::Display for Semaphore {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Semaphore({}) {:?}", self.count, self.wait_queue)
+        write!(
+            f,
+            "Semaphore({}) {:?} held by {:?}",
+            self.count, self.wait_queue, self.holders
+        )
     }
 }
@@ -0,0 +1,125 @@
+use alloc::collections::BTreeMap;
+use core::alloc::Layout;
+use syscall_def::mman::*;
+use x86_64::VirtAddr;
+
+use super::{
+    vma::{VmaList, MMAP_SIZE},
+    FrameAllocatorRef, MapperRef,
+};
+
+/// Size of every region reserved for `sys_allocate`, 1 MiB
+pub const USER_HEAP_CHUNK: u64 = 1024 * 1024;
+
+/// Smallest block handed out, sizes are rounded up to it
+const MIN_BLOCK: u64 = 8;
+
+/// Blocks for `sys_allocate`, carved out of anonymous mappings
+///
+/// the bookkeeping stays in the kernel so that a process cannot
+/// corrupt it, like the vmas it is shared by parent and child
+#[derive(Debug, Default)]
+pub struct UserAllocator {
+    /// free ranges by start address, with their size
+    free: BTreeMap<u64, u64>,
+    /// allocated blocks by address, with their size
+    used: BTreeMap<u64, u64>,
+}
+
+impl UserAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allocate(
+        &mut self,
+        layout: Layout,
+        vmas: &mut VmaList,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Option<VirtAddr> {
+        // the block and its alignment must fit in the mmap window
+        let size = (layout.size() as u64).checked_next_multiple_of(MIN_BLOCK)?;
+        let align = layout.align() as u64;
+        let reserve = size.checked_add(align).filter(|&reserve| reserve <= MMAP_SIZE)?;
+
+        let addr = match self.first_fit(size, align) {
+            Some(addr) => addr,
+            None => {
+                self.grow(reserve, vmas)?;
+                self.first_fit(size, align)?
+            }
+        };
+
+        // the kernel may be handed the block before the process touches it
+        let addr = VirtAddr::new(addr);
+        if !vmas.populate(addr, size, mapper, dealloc) {
+            self.release(addr.as_u64(), size);
+            return None;
+        }
+
+        self.used.insert(addr.as_u64(), size);
+        Some(addr)
+    }
+
+    /// Free a block, fails if it was not allocated with the size
+    pub fn deallocate(&mut self, addr: VirtAddr, layout: Layout) -> bool {
+        let addr = addr.as_u64();
+        let Some(size) = (layout.size() as u64).checked_next_multiple_of(MIN_BLOCK) else {
+            return false;
+        };
+        if self.used.get(&addr) != Some(&size) {
+            return false;
+        }
+
+        self.used.remove(&addr);
+        self.release(addr, size);
+        true
+    }
+
+    /// Take the range from the first free one that fits
+    fn first_fit(&mut self, size: u64, align: u64) -> Option<u64> {
+        let (start, len, addr) = self.free.iter().find_map(|(&start, &len)| {
+            let addr = start.checked_next_multiple_of(align)?;
+            (addr.checked_add(size)? <= start + len).then_some((start, len, addr))
+        })?;
+
+        self.free.remove(&start);
+        if addr > start {
+            self.free.insert(start, addr - start);
+        }
+        if addr + size < start + len {
+            self.free.insert(addr + size, start + len - addr - size);
+        }
+
+        Some(addr)
+    }
+
+    /// Give a range back, merged with the free ones next to it
+    fn release(&mut self, mut addr: u64, mut size: u64) {
+        if let Some((&prev, &len)) = self.free.range(..addr).next_back()
+            && prev + len == addr
+        {
+            self.free.remove(&prev);
+            addr = prev;
+            size += len;
+        }
+
+        if let Some(len) = self.free.remove(&(addr + size)) {
+            size += len;
+        }
+
+        self.free.insert(addr, size);
+    }
+
+    /// Reserve a new region of at least `size` bytes,
+    /// its pages are allocated when they are touched
+    fn grow(&mut self, size: u64, vmas: &mut VmaList) -> Option<()> {
+        let size = size.checked_next_multiple_of(USER_HEAP_CHUNK)?;
+        let start = vmas.reserve(size, PROT_READ | PROT_WRITE)?;
+
+        trace!("User heap grows by {:#x} at {:#x}", size, start.as_u64());
+        self.release(start.as_u64(), size);
+        Some(())
+    }
+}
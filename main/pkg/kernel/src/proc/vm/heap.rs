@@ -2,7 +2,7 @@ use core::sync::atomic::{AtomicU64, Ordering};
 
 use alloc::sync::Arc;
 use x86_64::{
-    structures::paging::{mapper::UnmapError, Page},
+    structures::paging::{page::PageRange, Page},
     VirtAddr,
 };
 
@@ -103,29 +103,15 @@ impl Heap {
         Some(new_end)
     }
 
-    pub(super) fn clean_up(
-        &self,
-        mapper: MapperRef,
-        dealloc: FrameAllocatorRef,
-    ) -> Result<(), UnmapError> {
-        if self.memory_usage() == 0 {
-            return Ok(());
-        }
-
-        // DONE: load the current end address and **reset it to base** (use `swap`)
-        let end_addr = self.end.swap(self.base.as_u64(), Ordering::Release);
+    /// Pages mapped for the heap, the page of the end is mapped too
+    pub fn pages(&self) -> PageRange {
+        let end = self.end.load(Ordering::Relaxed);
         let start_page = Page::containing_address(self.base);
-        let end_page = Page::containing_address(VirtAddr::new(end_addr));
-        let range = Page::range_inclusive(start_page, end_page);
-
-        // DONE: unmap the heap pages
-        elf::unmap_range(range, mapper, dealloc, true)?;
-
-        Ok(())
-    }
-
-    pub fn memory_usage(&self) -> u64 {
-        self.end.load(Ordering::Relaxed) - self.base.as_u64()
+        let mut end_page = Page::containing_address(VirtAddr::new(end));
+        if end != self.base.as_u64() {
+            end_page += 1;
+        }
+        Page::range(start_page, end_page)
     }
 }
 
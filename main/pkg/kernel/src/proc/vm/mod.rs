@@ -1,4 +1,8 @@
-use alloc::{format, vec::Vec};
+use alloc::{format, sync::Arc};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use syscall_def::mman::*;
 use x86_64::{
     structures::paging::{
         mapper::{CleanUp, UnmapError},
@@ -7,14 +11,22 @@ use x86_64::{
     },
     VirtAddr,
 };
-use xmas_elf::ElfFile;
+use xmas_elf::{program, ElfFile};
 use crate::{humanized_size, memory::*};
 use boot::KernelPages;
 
+pub mod allocator;
 pub mod heap;
+pub mod shm;
 pub mod stack;
+pub mod vma;
 
-use self::{heap::Heap, stack::Stack};
+use self::{
+    allocator::UserAllocator,
+    heap::Heap,
+    stack::Stack,
+    vma::{Vma, VmaKind, VmaList},
+};
 
 use super::PageTableContext;
 
@@ -24,7 +36,14 @@ use super::PageTableContext;
 // use boot::KernelPages;
 
 type MapperRef<'a> = &'a mut OffsetPageTable<'static>;
-type FrameAllocatorRef<'a> = &'a mut BootInfoFrameAllocator;
+type FrameAllocatorRef<'a> = &'a mut BuddyFrameAllocator;
+
+/// Whether user memory may be both writable and executable
+pub static ALLOW_WX: AtomicBool = AtomicBool::new(false);
+
+pub fn is_wx_allowed() -> bool {
+    ALLOW_WX.load(Ordering::Relaxed)
+}
 
 pub struct ProcessVm {
     // page table is shared by parent and child
@@ -36,10 +55,12 @@ pub struct ProcessVm {
     // heap is allocated by brk syscall
     pub(super) heap: Heap,
 
-    // code is hold by the first process
-    // these fields will be empty for other processes
-    pub(super) code: Vec<PageRangeInclusive>,
-    pub(super) code_usage: u64,
+    // code, heap, shared memory and mappings,
+    // shared by parent and child like the page table
+    pub(super) vmas: Arc<Mutex<VmaList>>,
+
+    // blocks handed out by the allocate syscall, kept in the vmas
+    pub(super) allocator: Arc<Mutex<UserAllocator>>,
 }
 
 impl ProcessVm {
@@ -48,8 +69,8 @@ impl ProcessVm {
             page_table,
             stack: Stack::empty(),
             heap: Heap::empty(),
-            code: Vec::new(),
-            code_usage: 0,
+            vmas: Arc::new(Mutex::new(VmaList::new())),
+            allocator: Arc::new(Mutex::new(UserAllocator::new())),
         }
     }
 
@@ -62,17 +83,12 @@ impl ProcessVm {
     /// NOTE: this function should only be called by the first process
     pub fn init_kernel_vm(mut self, pages: &KernelPages) -> Self {
         // DONE: record kernel code usage
-        let mut usage = 0;
-        let code = pages
-            .iter()
-            .map(|range| {
-                usage += range.size();
-                *range
-            })
-            .collect();
-
-        self.code = code;
-        self.code_usage = usage;
+        let mut vmas = self.vmas.lock();
+        for range in pages.iter() {
+            let range = Page::range(range.start, range.end + 1);
+            vmas.insert(Vma::new(range, PROT_READ | PROT_WRITE | PROT_EXEC, VmaKind::Code));
+        }
+        drop(vmas);
 
         self.stack = Stack::kstack();
 
@@ -82,7 +98,87 @@ impl ProcessVm {
     }
 
     pub fn brk(&self, addr: Option<VirtAddr>) -> Option<VirtAddr> {
-        self.heap.brk(
+        let mut vmas = self.vmas.lock();
+        let end = self.heap.brk(
+            addr,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )?;
+        vmas.set_heap(self.heap.pages());
+        Some(end)
+    }
+
+    pub fn mmap(
+        &self,
+        addr: Option<VirtAddr>,
+        len: u64,
+        prot: usize,
+        flags: usize,
+    ) -> Option<VirtAddr> {
+        self.vmas.lock().mmap(
+            addr,
+            len,
+            prot,
+            flags,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
+
+    pub fn munmap(&self, addr: VirtAddr, len: u64) -> bool {
+        let Ok(start) = Page::from_start_address(addr) else {
+            return false;
+        };
+        self.vmas.lock().munmap(
+            start,
+            len.div_ceil(PAGE_SIZE),
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
+
+    pub fn mprotect(&self, addr: VirtAddr, len: u64, prot: usize) -> bool {
+        let Ok(start) = Page::from_start_address(addr) else {
+            return false;
+        };
+        self.vmas.lock().mprotect(
+            start,
+            len.div_ceil(PAGE_SIZE),
+            prot,
+            &mut self.page_table.mapper(),
+        )
+    }
+
+    pub fn allocate(&self, layout: Layout) -> Option<VirtAddr> {
+        self.allocator.lock().allocate(
+            layout,
+            &mut self.vmas.lock(),
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
+
+    pub fn deallocate(&self, addr: VirtAddr, layout: Layout) -> bool {
+        self.allocator.lock().deallocate(addr, layout)
+    }
+
+    pub fn shm_create(&self, key: u32, size: u64) -> bool {
+        shm::create(key, size, &mut get_frame_alloc_for_sure())
+    }
+
+    pub fn shm_attach(&self, key: u32, addr: Option<VirtAddr>) -> Option<VirtAddr> {
+        shm::attach(
+            &mut self.vmas.lock(),
+            key,
+            addr,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
+
+    pub fn shm_detach(&self, addr: VirtAddr) -> bool {
+        shm::detach(
+            &mut self.vmas.lock(),
             addr,
             &mut self.page_table.mapper(),
             &mut get_frame_alloc_for_sure(),
@@ -100,10 +196,18 @@ impl ProcessVm {
 
     fn load_elf_code(&mut self, elf: &ElfFile, mapper: MapperRef, alloc: FrameAllocatorRef) {
         // DONE: make the `load_elf` function return the code pages
-        self.code = elf::load_elf(elf, *PHYSICAL_OFFSET.get().unwrap(), mapper, alloc, true).unwrap();
+        let code = elf::load_elf(elf, *PHYSICAL_OFFSET.get().unwrap(), mapper, alloc, true).unwrap();
 
         // DONE: calculate code usage
-        self.code_usage = self.code.iter().map(|range| range.size()).sum();
+        let segments = elf
+            .program_iter()
+            .filter(|segment| segment.get_type() == Ok(program::Type::Load));
+
+        let mut vmas = self.vmas.lock();
+        for (range, segment) in code.into_iter().zip(segments) {
+            let range = Page::range(range.start, range.end + 1);
+            vmas.insert(Vma::new(range, segment_prot(segment.flags()), VmaKind::Code));
+        }
     }
 
     pub fn fork(&self, stack_offset_count: u64) -> Self {
@@ -116,10 +220,8 @@ impl ProcessVm {
             page_table: owned_page_table,
             stack: self.stack.fork(mapper, alloc, stack_offset_count),
             heap: self.heap.fork(),
-
-            // do not share code info
-            code: Vec::new(),
-            code_usage: 0,
+            vmas: self.vmas.clone(),
+            allocator: self.allocator.clone(),
         }
     }
 
@@ -128,30 +230,27 @@ impl ProcessVm {
         let alloc = &mut *get_frame_alloc_for_sure();
 
         self.stack.handle_page_fault(addr, mapper, alloc)
+            || self.vmas.lock().handle_page_fault(addr, mapper, alloc)
     }
 
+    // the stack is the only area not shared with the other processes
     pub(super) fn memory_usage(&self) -> u64 {
-        self.stack.memory_usage() + self.heap.memory_usage() + self.code_usage
+        self.stack.memory_usage() + self.vmas.lock().memory_usage()
     }
 
     pub(super) fn clean_up(&mut self) -> Result<(), UnmapError> {
         let mapper = &mut self.page_table.mapper();
         let dealloc = &mut *get_frame_alloc_for_sure();
 
-        let start_count = dealloc.frames_recycled();
+        let start_count = dealloc.frames_free();
 
         // DONE: implement the `clean_up` function for `Stack`
         self.stack.clean_up(mapper, dealloc)?;
 
         if self.page_table.using_count() == 1 {
-            // free heap
-            // DONE: implement the `clean_up` function for `Heap`
-            self.heap.clean_up(mapper, dealloc)?;
-
-            // free code
-            for page_range in self.code.iter() {
-                elf::unmap_range(*page_range, mapper, dealloc, true)?;
-            }
+            // free code, heap and mappings, detach shared memory,
+            // the last user of a segment frees its frames
+            self.vmas.lock().clean_up(mapper, dealloc)?;
 
             unsafe {
                 // free P1-P3
@@ -163,7 +262,7 @@ impl ProcessVm {
         }
 
         // DONE: maybe print how many frames are recycled
-        let end_count = dealloc.frames_recycled();
+        let end_count = dealloc.frames_free();
 
         debug!("Recycled {} frames.", end_count - start_count);
 
@@ -171,6 +270,29 @@ impl ProcessVm {
     }
 }
 
+/// Whether a loadable segment is both writable and executable
+pub fn has_wx_segment(elf: &ElfFile) -> bool {
+    elf.program_iter().any(|segment| {
+        let flags = segment.flags();
+        segment.get_type() == Ok(program::Type::Load) && flags.is_write() && flags.is_execute()
+    })
+}
+
+/// Protection of a loaded segment
+fn segment_prot(flags: program::Flags) -> usize {
+    let mut prot = PROT_NONE;
+    if flags.is_read() {
+        prot |= PROT_READ;
+    }
+    if flags.is_write() {
+        prot |= PROT_WRITE;
+    }
+    if flags.is_execute() {
+        prot |= PROT_EXEC;
+    }
+    prot
+}
+
 impl core::fmt::Debug for ProcessVm {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let (size, unit) = humanized_size(self.memory_usage());
@@ -0,0 +1,150 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::Mutex;
+use syscall_def::mman::*;
+use x86_64::{
+    structures::paging::{page::*, *},
+    VirtAddr,
+};
+
+use super::{
+    vma::{Vma, VmaKind, VmaList},
+    FrameAllocatorRef, MapperRef,
+};
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+
+// shared memory segments are attached in this window
+// 0x100000000 bytes -> 4GiB
+// from 0x0000_3000_0000_0000 to 0x0000_3000_ffff_ffff
+pub const SHM_START: u64 = 0x3000_0000_0000;
+pub const SHM_PAGES: u64 = 0x100000;
+pub const SHM_SIZE: u64 = SHM_PAGES * PAGE_SIZE;
+
+/// Largest segment that can be created
+pub const SHM_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Segments by key, a segment is dropped from here
+/// when its last user detaches
+static SEGMENTS: Mutex<BTreeMap<u32, Arc<SharedSegment>>> = Mutex::new(BTreeMap::new());
+
+/// Physical frames shared by every process that attaches the segment
+#[derive(Debug)]
+pub struct SharedSegment {
+    key: u32,
+    frames: Vec<PhysFrame>,
+}
+
+/// Create a zeroed segment, succeeds if one with the same size exists
+pub fn create(key: u32, size: u64, alloc: FrameAllocatorRef) -> bool {
+    if size == 0 || size > SHM_MAX_SIZE {
+        return false;
+    }
+
+    let pages = size.div_ceil(PAGE_SIZE);
+
+    let mut segments = SEGMENTS.lock();
+    if let Some(segment) = segments.get(&key) {
+        return segment.frames.len() as u64 == pages;
+    }
+
+    let mut frames = Vec::with_capacity(pages as usize);
+    for _ in 0..pages {
+        let Some(frame) = alloc.allocate_frame() else {
+            for frame in frames {
+                unsafe { alloc.deallocate_frame(frame) };
+            }
+            return false;
+        };
+
+        unsafe {
+            let ptr = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+            core::ptr::write_bytes(ptr, 0, PAGE_SIZE as usize);
+        }
+        frames.push(frame);
+    }
+
+    trace!("Created shared memory #{:#x} with {} pages", key, pages);
+    segments.insert(key, Arc::new(SharedSegment { key, frames }));
+    true
+}
+
+/// Drop a reference to the segment, the frames are freed with the last one
+pub(super) fn release(segment: Arc<SharedSegment>, dealloc: FrameAllocatorRef) {
+    let mut segments = SEGMENTS.lock();
+    // only the registry and this reference are left
+    if Arc::strong_count(&segment) == 2 {
+        segments.remove(&segment.key);
+    }
+    drop(segments);
+
+    if let Ok(segment) = Arc::try_unwrap(segment) {
+        trace!("Freed shared memory #{:#x}", segment.key);
+        for frame in segment.frames {
+            unsafe { dealloc.deallocate_frame(frame) };
+        }
+    }
+}
+
+/// Map the segment at `addr`, or at the first free range in the window
+pub fn attach(
+    vmas: &mut VmaList,
+    key: u32,
+    addr: Option<VirtAddr>,
+    mapper: MapperRef,
+    alloc: FrameAllocatorRef,
+) -> Option<VirtAddr> {
+    let segment = SEGMENTS.lock().get(&key)?.clone();
+    let pages = segment.frames.len() as u64;
+
+    let start = match addr {
+        Some(addr) => {
+            let start = Page::from_start_address(addr).ok()?;
+            if !vmas.is_free(start, pages, SHM_START, SHM_SIZE) {
+                return None;
+            }
+            start
+        }
+        None => vmas.find_free(pages, SHM_START, SHM_SIZE)?,
+    };
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::NO_EXECUTE;
+
+    for (i, frame) in segment.frames.iter().enumerate() {
+        let page = start + i as u64;
+        match unsafe { mapper.map_to(page, *frame, flags, alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                warn!("Failed to attach shared memory #{:#x}: {:?}", key, err);
+                if i > 0 {
+                    let mapped = Page::range_inclusive(start, page - 1);
+                    elf::unmap_range(mapped, mapper, alloc, false).ok();
+                }
+                return None;
+            }
+        }
+    }
+
+    let range = Page::range(start, start + pages);
+    vmas.insert(Vma::new(range, PROT_READ | PROT_WRITE, VmaKind::Shm(segment)));
+
+    Some(start.start_address())
+}
+
+/// Unmap the segment attached at `addr`
+pub fn detach(
+    vmas: &mut VmaList,
+    addr: VirtAddr,
+    mapper: MapperRef,
+    dealloc: FrameAllocatorRef,
+) -> bool {
+    let Some(vma) = vmas.take(addr, |kind| matches!(kind, VmaKind::Shm(_))) else {
+        return false;
+    };
+
+    if let Err(err) = vma.clean_up(mapper, dealloc) {
+        warn!("Failed to detach shared memory at {:#x}: {:?}", addr, err);
+    }
+    true
+}
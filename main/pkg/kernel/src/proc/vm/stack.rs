@@ -143,13 +143,13 @@ impl Stack {
     /// - `size`: the count of pages to be cloned
     fn clone_range(&self, cur_addr: u64, dest_addr: u64, size: u64) {
         trace!("Clone range: {:#x} -> {:#x}", cur_addr, dest_addr);
-        unsafe {
+        crate::memory::with_user_access(|| unsafe {
             core::ptr::copy_nonoverlapping::<u64>(
                 cur_addr as *mut u64,
                 dest_addr as *mut u64,
                 (size * Size4KiB::SIZE / 8) as usize,
             );
-        }
+        });
     }
 
     pub fn fork(
@@ -0,0 +1,496 @@
+use alloc::{sync::Arc, vec::Vec};
+use syscall_def::mman::*;
+use x86_64::{
+    structures::paging::{
+        mapper::{FlagUpdateError, UnmapError},
+        page::*,
+        *,
+    },
+    VirtAddr,
+};
+
+use super::{
+    shm::{self, SharedSegment},
+    FrameAllocatorRef, MapperRef,
+};
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+
+// anonymous mappings are placed in this window
+// 0x1000000000 bytes -> 64GiB
+// from 0x0000_1000_0000_0000 to 0x0000_100f_ffff_ffff
+pub const MMAP_START: u64 = 0x1000_0000_0000;
+pub const MMAP_PAGES: u64 = 0x1000000;
+pub const MMAP_SIZE: u64 = MMAP_PAGES * PAGE_SIZE;
+
+#[derive(Debug, Clone)]
+pub enum VmaKind {
+    /// segments of the program
+    Code,
+    /// grown and shrunk by brk
+    Heap,
+    /// an attached shared memory segment
+    Shm(Arc<SharedSegment>),
+    /// zeroed pages allocated on the first access
+    ///
+    /// processes forked from each other share the page table,
+    /// so both kinds are seen by all of them
+    Anonymous { shared: bool },
+    /// zeroed pages like anonymous ones, reserved by the allocator
+    /// of `sys_allocate`, the process cannot unmap or protect them
+    Allocator,
+}
+
+/// A range of pages of an address space, the range is [start, end)
+#[derive(Debug)]
+pub struct Vma {
+    start: Page,
+    end: Page,
+    /// `PROT_*` bits
+    prot: usize,
+    kind: VmaKind,
+    /// pages mapped to a frame
+    resident: u64,
+}
+
+impl Vma {
+    /// An area with every page mapped
+    pub fn new(range: PageRange, prot: usize, kind: VmaKind) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+            prot,
+            kind,
+            resident: range.count() as u64,
+        }
+    }
+
+    pub fn contains(&self, page: Page) -> bool {
+        self.start <= page && page < self.end
+    }
+
+    fn overlaps(&self, start: Page, end: Page) -> bool {
+        start < self.end && self.start < end
+    }
+
+    fn is_anonymous(&self) -> bool {
+        matches!(self.kind, VmaKind::Anonymous { .. } | VmaKind::Allocator)
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        self.resident * PAGE_SIZE
+    }
+
+    /// Flags of the pages, inaccessible pages stay mapped for the kernel only
+    fn flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT;
+        if self.prot != PROT_NONE {
+            flags |= PageTableFlags::USER_ACCESSIBLE;
+        }
+        if self.prot & PROT_WRITE != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if self.prot & PROT_EXEC == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+
+    /// Keep [start, at) and return [at, end)
+    fn split_off(&mut self, at: Page, mapper: &OffsetPageTable) -> Vma {
+        debug_assert!(self.start < at && at < self.end, "Split outside of the area.");
+
+        let kept = if self.resident == 0 {
+            0
+        } else {
+            Page::range(self.start, at)
+                .filter(|page| mapper.translate_page(*page).is_ok())
+                .count() as u64
+        };
+
+        let right = Vma {
+            start: at,
+            end: self.end,
+            prot: self.prot,
+            kind: self.kind.clone(),
+            resident: self.resident - kept,
+        };
+
+        self.end = at;
+        self.resident = kept;
+        right
+    }
+
+    /// Apply the protection to the pages already mapped
+    fn protect(&mut self, prot: usize, mapper: MapperRef) -> Result<(), FlagUpdateError> {
+        self.prot = prot;
+        let flags = self.flags();
+
+        let mut left = self.resident;
+        for page in Page::range(self.start, self.end) {
+            if left == 0 {
+                break;
+            }
+            match unsafe { mapper.update_flags(page, flags) } {
+                Ok(flush) => {
+                    flush.flush();
+                    left -= 1;
+                }
+                Err(FlagUpdateError::PageNotMapped) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unmap the pages, the frames of a shared memory segment
+    /// are freed by its last user
+    pub fn clean_up(self, mapper: MapperRef, dealloc: FrameAllocatorRef) -> Result<(), UnmapError> {
+        let owned = !matches!(self.kind, VmaKind::Shm(_));
+
+        let mut left = self.resident;
+        for page in Page::range(self.start, self.end) {
+            if left == 0 {
+                break;
+            }
+            match mapper.unmap(page) {
+                Ok((frame, flush)) => {
+                    if owned {
+                        unsafe { dealloc.deallocate_frame(frame) };
+                    }
+                    flush.flush();
+                    left -= 1;
+                }
+                Err(UnmapError::PageNotMapped) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if let VmaKind::Shm(segment) = self.kind {
+            shm::release(segment, dealloc);
+        }
+
+        Ok(())
+    }
+}
+
+/// Areas mapped in an address space
+///
+/// like the page table, it is shared by parent and child
+#[derive(Debug, Default)]
+pub struct VmaList {
+    /// sorted by the start page
+    vmas: Vec<Vma>,
+}
+
+impl VmaList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, vma: Vma) {
+        let index = self.vmas.partition_point(|v| v.start < vma.start);
+        self.vmas.insert(index, vma);
+    }
+
+    /// Remove the area of the kind starting at `addr`
+    pub fn take(&mut self, addr: VirtAddr, kind: fn(&VmaKind) -> bool) -> Option<Vma> {
+        let index = self
+            .vmas
+            .iter()
+            .position(|v| v.start.start_address() == addr && kind(&v.kind))?;
+        Some(self.vmas.remove(index))
+    }
+
+    /// Whether `pages` pages at `addr` are in the window, without overflow
+    fn fits(addr: u64, pages: u64, window: u64, size: u64) -> bool {
+        pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|len| addr.checked_add(len))
+            .is_some_and(|end| addr >= window && end <= window + size)
+    }
+
+    /// Whether `pages` pages at `start` are in the window and not used
+    pub fn is_free(&self, start: Page, pages: u64, window: u64, size: u64) -> bool {
+        Self::fits(start.start_address().as_u64(), pages, window, size)
+            && !self.vmas.iter().any(|v| v.overlaps(start, start + pages))
+    }
+
+    /// The first range of `pages` free pages in the window
+    pub fn find_free(&self, pages: u64, window: u64, size: u64) -> Option<Page> {
+        if pages > size / PAGE_SIZE {
+            return None;
+        }
+
+        let mut start = Page::containing_address(VirtAddr::new(window));
+        for vma in self.vmas.iter() {
+            if vma.end <= start {
+                continue;
+            }
+            // cannot overflow, the range is at most as large as the window
+            let end = start.start_address().as_u64() + pages * PAGE_SIZE;
+            if end <= vma.start.start_address().as_u64() {
+                break;
+            }
+            start = start.max(vma.end);
+        }
+
+        self.is_free(start, pages, window, size).then_some(start)
+    }
+
+    /// Make the heap area cover the pages mapped by brk
+    pub fn set_heap(&mut self, range: PageRange) {
+        self.vmas.retain(|v| !matches!(v.kind, VmaKind::Heap));
+        if !range.is_empty() {
+            self.insert(Vma::new(range, PROT_READ | PROT_WRITE, VmaKind::Heap));
+        }
+    }
+
+    /// Map a zeroed frame for an untouched page of an anonymous mapping
+    pub fn handle_page_fault(
+        &mut self,
+        addr: VirtAddr,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> bool {
+        let page = Page::containing_address(addr);
+        let Some(vma) = self
+            .vmas
+            .iter_mut()
+            .find(|v| v.contains(page) && v.is_anonymous())
+        else {
+            return false;
+        };
+
+        if vma.prot == PROT_NONE {
+            warn!("Access to inaccessible mapping at {:#x}", addr);
+            return false;
+        }
+
+        let Some(frame) = alloc.allocate_frame() else {
+            return false;
+        };
+
+        unsafe {
+            let ptr = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+            core::ptr::write_bytes(ptr, 0, PAGE_SIZE as usize);
+        }
+
+        match unsafe { mapper.map_to(page, frame, vma.flags(), alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                warn!("Failed to map {:#x}: {:?}", addr, err);
+                unsafe { alloc.deallocate_frame(frame) };
+                return false;
+            }
+        }
+
+        vma.resident += 1;
+        true
+    }
+
+    /// Map the untouched pages of [addr, addr + len) in anonymous mappings
+    pub fn populate(
+        &mut self,
+        addr: VirtAddr,
+        len: u64,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> bool {
+        let start: Page = Page::containing_address(addr);
+        let end = Page::containing_address(addr + len.max(1) - 1u64);
+        Page::range_inclusive(start, end).all(|page| {
+            mapper.translate_page(page).is_ok()
+                || self.handle_page_fault(page.start_address(), mapper, alloc)
+        })
+    }
+
+    /// Make a boundary between areas at `at`
+    fn split(&mut self, at: Page, mapper: &OffsetPageTable) {
+        if let Some(index) = self.vmas.iter().position(|v| v.start < at && at < v.end) {
+            let right = self.vmas[index].split_off(at, mapper);
+            self.vmas.insert(index + 1, right);
+        }
+    }
+
+    /// Reserve an anonymous mapping, its pages are allocated on access
+    ///
+    /// `addr` is a hint unless `MAP_FIXED` is set
+    pub fn mmap(
+        &mut self,
+        addr: Option<VirtAddr>,
+        len: u64,
+        prot: usize,
+        flags: usize,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Option<VirtAddr> {
+        if len == 0 || len > MMAP_SIZE || !Self::is_valid_prot(prot) {
+            return None;
+        }
+        if flags & MAP_ANONYMOUS == 0 {
+            warn!("Only anonymous mappings are supported.");
+            return None;
+        }
+        let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+            MAP_SHARED => true,
+            MAP_PRIVATE => false,
+            _ => return None,
+        };
+
+        let pages = len.div_ceil(PAGE_SIZE);
+        let fixed = flags & MAP_FIXED != 0;
+
+        let start = match addr.map(Page::from_start_address) {
+            Some(Ok(start)) if fixed => {
+                if !self.munmap(start, pages, mapper, dealloc) {
+                    return None;
+                }
+                start
+            }
+            _ if fixed => return None,
+            Some(Ok(start)) if self.is_free(start, pages, MMAP_START, MMAP_SIZE) => start,
+            _ => self.find_free(pages, MMAP_START, MMAP_SIZE)?,
+        };
+
+        trace!(
+            "Mmap: {:#x} ({} pages), prot {:#x}, shared: {}",
+            start.start_address().as_u64(),
+            pages,
+            prot,
+            shared
+        );
+
+        self.insert(Vma {
+            start,
+            end: start + pages,
+            prot,
+            kind: VmaKind::Anonymous { shared },
+            resident: 0,
+        });
+
+        Some(start.start_address())
+    }
+
+    /// Reserve a region for the allocator, its pages are allocated on access
+    pub fn reserve(&mut self, len: u64, prot: usize) -> Option<VirtAddr> {
+        let pages = len.div_ceil(PAGE_SIZE);
+        let start = self.find_free(pages, MMAP_START, MMAP_SIZE)?;
+
+        self.insert(Vma {
+            start,
+            end: start + pages,
+            prot,
+            kind: VmaKind::Allocator,
+            resident: 0,
+        });
+
+        Some(start.start_address())
+    }
+
+    /// Whether the range overlaps a region of the allocator
+    fn has_allocator(&self, start: Page, end: Page) -> bool {
+        self.vmas
+            .iter()
+            .any(|v| v.overlaps(start, end) && matches!(v.kind, VmaKind::Allocator))
+    }
+
+    /// Remove the anonymous mappings in the range, parts of them are kept
+    pub fn munmap(
+        &mut self,
+        start: Page,
+        pages: u64,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> bool {
+        // only anonymous mappings live in the window
+        if pages == 0 || !Self::in_mmap_window(start, pages) {
+            return false;
+        }
+
+        // the allocator keeps handing out its free ranges
+        let end = start + pages;
+        if self.has_allocator(start, end) {
+            return false;
+        }
+
+        self.split(start, mapper);
+        self.split(end, mapper);
+
+        let (removed, kept) = core::mem::take(&mut self.vmas)
+            .into_iter()
+            .partition(|v| start <= v.start && v.end <= end);
+        self.vmas = kept;
+
+        for vma in removed {
+            if let Err(err) = vma.clean_up(mapper, dealloc) {
+                warn!("Failed to unmap {:#x}: {:?}", start.start_address(), err);
+            }
+        }
+
+        true
+    }
+
+    /// Change the protection of the range, which must be mapped
+    pub fn mprotect(&mut self, start: Page, pages: u64, prot: usize, mapper: MapperRef) -> bool {
+        if pages == 0 || !Self::is_valid_prot(prot) || !Self::in_mmap_window(start, pages) {
+            return false;
+        }
+
+        let end = start + pages;
+        if self.has_allocator(start, end) {
+            return false;
+        }
+
+        // no holes are allowed in the range
+        let mut covered = start;
+        for vma in self.vmas.iter().filter(|v| v.overlaps(start, end)) {
+            if vma.start > covered {
+                return false;
+            }
+            covered = vma.end;
+        }
+        if covered < end {
+            return false;
+        }
+
+        self.split(start, mapper);
+        self.split(end, mapper);
+
+        for vma in self.vmas.iter_mut().filter(|v| v.overlaps(start, end)) {
+            if let Err(err) = vma.protect(prot, mapper) {
+                warn!("Failed to protect {:#x}: {:?}", vma.start.start_address(), err);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Only known bits, and not both writable and executable unless allowed
+    fn is_valid_prot(prot: usize) -> bool {
+        let wx = PROT_WRITE | PROT_EXEC;
+        prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) == 0
+            && (prot & wx != wx || super::is_wx_allowed())
+    }
+
+    fn in_mmap_window(start: Page, pages: u64) -> bool {
+        Self::fits(start.start_address().as_u64(), pages, MMAP_START, MMAP_SIZE)
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        self.vmas.iter().map(Vma::memory_usage).sum()
+    }
+
+    pub fn clean_up(
+        &mut self,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Result<(), UnmapError> {
+        for vma in core::mem::take(&mut self.vmas) {
+            vma.clean_up(mapper, dealloc)?;
+        }
+
+        Ok(())
+    }
+}
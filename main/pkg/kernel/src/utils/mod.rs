@@ -6,6 +6,7 @@ mod regs;
 //pub mod clock;
 pub mod func;
 pub mod logger;
+pub mod pipe;
 pub mod resource;
 
 pub use macros::*;
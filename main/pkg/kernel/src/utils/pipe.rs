@@ -0,0 +1,162 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/// Capacity of the pipe buffer in bytes
+pub const PIPE_SIZE: usize = 4096;
+
+#[derive(Debug)]
+struct PipeBuffer {
+    buf: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+}
+
+/// Bounded ring buffer shared by the two ends of a pipe
+#[derive(Debug)]
+pub struct Pipe {
+    inner: Mutex<PipeBuffer>,
+}
+
+impl Pipe {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(PipeBuffer {
+                buf: VecDeque::with_capacity(PIPE_SIZE),
+                readers: 0,
+                writers: 0,
+            }),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Read,
+    Write,
+}
+
+/// One end of a pipe, the pipe is closed for the other side
+/// once every copy of this end has been dropped
+#[derive(Debug)]
+pub struct PipeEnd {
+    pipe: Arc<Pipe>,
+    end: End,
+}
+
+/// Create a pipe, returns the read and the write end
+pub fn pipe() -> (PipeEnd, PipeEnd) {
+    let pipe = Pipe::new();
+    (PipeEnd::reader(pipe.clone()), PipeEnd::writer(pipe))
+}
+
+impl PipeEnd {
+    pub fn reader(pipe: Arc<Pipe>) -> Self {
+        Self::new(pipe, End::Read)
+    }
+
+    pub fn writer(pipe: Arc<Pipe>) -> Self {
+        Self::new(pipe, End::Write)
+    }
+
+    fn new(pipe: Arc<Pipe>, end: End) -> Self {
+        let mut inner = pipe.inner.lock();
+        match end {
+            End::Read => inner.readers += 1,
+            End::Write => inner.writers += 1,
+        }
+        drop(inner);
+
+        let end = Self { pipe, end };
+        // someone may be waiting in open for this end to show up
+        crate::proc::wake_channel(end.open_channel());
+        end
+    }
+
+    pub fn is_writer(&self) -> bool {
+        self.end == End::Write
+    }
+
+    /// Whether the other end is open
+    pub fn has_peer(&self) -> bool {
+        let inner = self.pipe.inner.lock();
+        match self.end {
+            End::Read => inner.writers > 0,
+            End::Write => inner.readers > 0,
+        }
+    }
+
+    /// Processes blocked on either end wait on this channel
+    pub fn wait_channel(&self) -> usize {
+        Arc::as_ptr(&self.pipe) as usize
+    }
+
+    /// Processes opening a FIFO wait on this channel for the other end,
+    /// the pipe is aligned so it does not clash with any `wait_channel`
+    pub fn open_channel(&self) -> usize {
+        self.wait_channel() + 1
+    }
+
+    /// Whether a read or write has to wait for the other side
+    pub fn would_block(&self) -> bool {
+        let inner = self.pipe.inner.lock();
+        match self.end {
+            End::Read => inner.buf.is_empty() && inner.writers > 0,
+            End::Write => inner.buf.len() == PIPE_SIZE && inner.readers > 0,
+        }
+    }
+
+    /// Read what is buffered, 0 at the end of file
+    pub fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if self.end != End::Read {
+            return None;
+        }
+
+        let mut inner = self.pipe.inner.lock();
+        let len = buf.len().min(inner.buf.len());
+        for (dst, src) in buf.iter_mut().zip(inner.buf.drain(..len)) {
+            *dst = src;
+        }
+        drop(inner);
+
+        if len > 0 {
+            crate::proc::wake_channel(self.wait_channel());
+        }
+        Some(len)
+    }
+
+    /// Write as much as fits, fails if there is no reader left
+    pub fn write(&self, buf: &[u8]) -> Option<usize> {
+        if self.end != End::Write {
+            return None;
+        }
+
+        let mut inner = self.pipe.inner.lock();
+        if inner.readers == 0 {
+            return None;
+        }
+
+        let len = buf.len().min(PIPE_SIZE - inner.buf.len());
+        inner.buf.extend(&buf[..len]);
+        drop(inner);
+
+        if len > 0 {
+            crate::proc::wake_channel(self.wait_channel());
+        }
+        Some(len)
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut inner = self.pipe.inner.lock();
+        match self.end {
+            End::Read => inner.readers -= 1,
+            End::Write => inner.writers -= 1,
+        }
+        drop(inner);
+
+        // the other side sees the end of file or a broken pipe
+        crate::proc::wake_channel(self.wait_channel());
+    }
+}
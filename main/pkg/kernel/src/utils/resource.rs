@@ -1,6 +1,8 @@
-use crate::drivers::input::*;
+use crate::drivers::tty;
+use crate::pipe::PipeEnd;
 use storage::FileHandle;
-use alloc::{collections::BTreeMap, string::String};
+use syscall_def::tty::Termios;
+use alloc::{collections::{BTreeMap, BTreeSet}, string::String, sync::Arc};
 use spin::Mutex;
 
 #[derive(Debug, Clone)]
@@ -10,28 +12,56 @@ pub enum StdIO {
     Stderr,
 }
 
-#[derive(Debug)]
+/// Returned by `ResourceSet::read` and `write` when the caller has to wait
+pub const WOULD_BLOCK: isize = -2;
+
+/// Processes blocked on the console wait on this channel
+pub const CONSOLE_CHANNEL: usize = 0;
+
+/// Open fds per process
+pub const MAX_FDS: usize = 64;
+
+/// Open resources of a process
+///
+/// a clone shares the resources behind the fds, as inherited on spawn
+#[derive(Debug, Clone)]
 pub struct ResourceSet {
-    pub handles: BTreeMap<u8, Mutex<Resource>>,
+    pub handles: BTreeMap<u8, Arc<Mutex<Resource>>>,
+    /// fds opened with `O_NONBLOCK`
+    nonblocking: BTreeSet<u8>,
+    /// fds opened with `O_CLOEXEC`, they are not inherited on spawn
+    cloexec: BTreeSet<u8>,
 }
 
 impl Default for ResourceSet {
     fn default() -> Self {
-        let mut res = Self {
-            handles: BTreeMap::new(),
-        };
-
-        res.open(Resource::Console(StdIO::Stdin));
-        res.open(Resource::Console(StdIO::Stdout));
-        res.open(Resource::Console(StdIO::Stderr));
+        let stdio = [StdIO::Stdin, StdIO::Stdout, StdIO::Stderr];
+        let handles = stdio
+            .into_iter()
+            .enumerate()
+            .map(|(fd, stdio)| (fd as u8, Arc::new(Mutex::new(Resource::Console(stdio)))))
+            .collect();
 
-        res
+        Self {
+            handles,
+            nonblocking: BTreeSet::new(),
+            cloexec: BTreeSet::new(),
+        }
     }
 }
 
 impl ResourceSet {
     pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().read(buf)) {
+        let Some(handle) = self.handles.get(&fd) else {
+            return -1;
+        };
+
+        let mut res = handle.lock();
+        if res.read_blocks() && !self.nonblocking.contains(&fd) {
+            return WOULD_BLOCK;
+        }
+
+        if let Some(count) = res.read(buf) {
             count as isize
         } else {
             -1
@@ -39,20 +69,127 @@ impl ResourceSet {
     }
 
     pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().write(buf)) {
+        let Some(handle) = self.handles.get(&fd) else {
+            return -1;
+        };
+
+        let mut res = handle.lock();
+        if res.write_blocks() && !self.nonblocking.contains(&fd) {
+            return WOULD_BLOCK;
+        }
+
+        if let Some(count) = res.write(buf) {
             count as isize
         } else {
             -1
         }
     }
 
-    pub fn open(&mut self, res: Resource) -> u8 {
-        let fd = self.handles.len() as u8;
-        self.handles.insert(fd, Mutex::new(res));
-        fd
+    /// Channel to wait on until the other side of `fd` is opened,
+    /// `None` if it can be used right away
+    pub fn open_channel(&self, fd: u8) -> Option<usize> {
+        if self.nonblocking.contains(&fd) {
+            return None;
+        }
+
+        match &*self.handles.get(&fd)?.lock() {
+            Resource::Pipe(pipe) if !pipe.has_peer() => Some(pipe.open_channel()),
+            _ => None,
+        }
+    }
+
+    /// Channel to wait on when `read` or `write` would block
+    pub fn wait_channel(&self, fd: u8) -> Option<usize> {
+        self.handles.get(&fd).map(|h| h.lock().wait_channel())
+    }
+
+    pub fn ioctl(&self, fd: u8, request: usize, termios: &mut Termios) -> isize {
+        if let Some(ret) = self.handles.get(&fd).and_then(|h| h.lock().ioctl(request, termios)) {
+            ret as isize
+        } else {
+            -1
+        }
+    }
+
+    /// The lowest fd that is not in use
+    fn free_fd(&self) -> Option<u8> {
+        (0..MAX_FDS as u8).find(|fd| !self.handles.contains_key(fd))
+    }
+
+    /// Returns `None` if the process has too many open fds
+    pub fn open(&mut self, res: Resource) -> Option<u8> {
+        let fd = self.free_fd()?;
+        self.handles.insert(fd, Arc::new(Mutex::new(res)));
+        Some(fd)
+    }
+
+    /// Duplicate `fd` to the lowest free fd, both share the resource
+    pub fn dup(&mut self, fd: u8) -> Option<u8> {
+        let new_fd = self.free_fd()?;
+        self.dup2(fd, new_fd)
+    }
+
+    /// Make `new_fd` refer to the resource of `fd`, closing it first if needed
+    ///
+    /// the copy is inherited on spawn even if `fd` is not
+    pub fn dup2(&mut self, fd: u8, new_fd: u8) -> Option<u8> {
+        if new_fd as usize >= MAX_FDS {
+            return None;
+        }
+
+        let handle = self.handles.get(&fd)?.clone();
+        let nonblocking = self.nonblocking.contains(&fd);
+
+        self.handles.insert(new_fd, handle);
+        self.set_nonblocking(new_fd, nonblocking);
+        self.cloexec.remove(&new_fd);
+        Some(new_fd)
+    }
+
+    /// The fds of a spawned process, all but the `O_CLOEXEC` ones
+    pub fn inherited(&self) -> Self {
+        let mut set = self.clone();
+        for fd in self.cloexec.iter() {
+            set.close(*fd);
+        }
+        set
+    }
+
+    /// Make `fd` refer to the same resource as `src` of another set
+    pub fn redirect(&mut self, fd: u8, src: &ResourceSet, src_fd: u8) -> bool {
+        let Some(handle) = src.handles.get(&src_fd) else {
+            return false;
+        };
+
+        self.handles.insert(fd, handle.clone());
+        if src.nonblocking.contains(&src_fd) {
+            self.nonblocking.insert(fd);
+        } else {
+            self.nonblocking.remove(&fd);
+        }
+        self.cloexec.remove(&fd);
+        true
+    }
+
+    pub fn set_nonblocking(&mut self, fd: u8, nonblocking: bool) {
+        if nonblocking {
+            self.nonblocking.insert(fd);
+        } else {
+            self.nonblocking.remove(&fd);
+        }
+    }
+
+    pub fn set_cloexec(&mut self, fd: u8, cloexec: bool) {
+        if cloexec {
+            self.cloexec.insert(fd);
+        } else {
+            self.cloexec.remove(&fd);
+        }
     }
 
     pub fn close(&mut self, fd: u8) -> bool {
+        self.nonblocking.remove(&fd);
+        self.cloexec.remove(&fd);
         self.handles.remove(&fd).is_some()
     }
 }
@@ -61,29 +198,56 @@ impl ResourceSet {
 pub enum Resource {
     Console(StdIO),
     File(FileHandle),
+    Pipe(PipeEnd),
     Null,
 }
 
 impl Resource {
+    /// Whether a read would have to wait for data
+    pub fn read_blocks(&self) -> bool {
+        match self {
+            Resource::Console(StdIO::Stdin) => !tty::can_read(),
+            Resource::Pipe(pipe) => pipe.would_block(),
+            _ => false,
+        }
+    }
+
+    /// Whether a write would have to wait for space
+    pub fn write_blocks(&self) -> bool {
+        match self {
+            Resource::Pipe(pipe) => pipe.would_block(),
+            _ => false,
+        }
+    }
+
+    pub fn wait_channel(&self) -> usize {
+        match self {
+            Resource::Pipe(pipe) => pipe.wait_channel(),
+            _ => CONSOLE_CHANNEL,
+        }
+    }
+
     pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
         match self {
             Resource::Console(stdio) => match stdio {
-                StdIO::Stdin => {
-                    // DONE: just read from kernel input buffer
-                    if let Some(ch) = try_pop_key() {
-                        buf[0] = ch;
-                        Some(1)
-                    } else {
-                        Some(0)
-                    }
-                }
+                // DONE: read what the line discipline has made available
+                StdIO::Stdin => Some(tty::read(buf)),
                 _ => None,
             },
             Resource::File(file) => file.read(buf).ok(),
+            Resource::Pipe(pipe) => pipe.read(buf),
             Resource::Null => Some(0),
         }
     }
 
+    /// Device specific requests, only the console supports any
+    pub fn ioctl(&mut self, request: usize, termios: &mut Termios) -> Option<usize> {
+        match self {
+            Resource::Console(_) => tty::ioctl(request, termios),
+            _ => None,
+        }
+    }
+
     pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
         match self {
             Resource::Console(stdio) => match *stdio {
@@ -98,6 +262,7 @@ impl Resource {
                 }
             },
             Resource::File(_) => None,
+            Resource::Pipe(pipe) => pipe.write(buf),
             Resource::Null => Some(buf.len()),
         }
     }
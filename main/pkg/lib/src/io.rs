@@ -1,9 +1,11 @@
+use crate::sys_ioctl;
 use crate::sys_read;
 use crate::sys_write;
-use crate::print;
 use alloc::string::{String, ToString};
 use alloc::vec;
 
+pub use syscall_def::tty::*;
+
 pub struct Stdin;
 pub struct Stdout;
 pub struct Stderr;
@@ -13,93 +15,44 @@ impl Stdin {
         Self
     }
 
-    fn is_utf8(ch: u8) -> bool {
-        ch & 0x80 == 0 || ch & 0xE0 == 0xC0 || ch & 0xF0 == 0xE0 || ch & 0xF8 == 0xF0
-    }
-
-    fn to_utf8(&self, ch: u8) -> u32 {
-        let mut codepoint = 0;
-
-        if ch & 0x80 == 0 {
-            codepoint = ch as u32;
-        } else if ch & 0xE0 == 0xC0 {
-            codepoint = ((ch & 0x1F) as u32) << 6;
-            codepoint |= (self.pop_key() & 0x3F) as u32;
-        } else if ch & 0xF0 == 0xE0 {
-            codepoint = ((ch & 0x0F) as u32) << 12;
-            codepoint |= ((self.pop_key() & 0x3F) as u32) << 6;
-            codepoint |= (self.pop_key() & 0x3F) as u32;
-        } else if ch & 0xF8 == 0xF0 {
-            codepoint = ((ch & 0x07) as u32) << 18;
-            codepoint |= ((self.pop_key() & 0x3F) as u32) << 12;
-            codepoint |= ((self.pop_key() & 0x3F) as u32) << 6;
-            codepoint |= (self.pop_key() & 0x3F) as u32;
-        }
-
-        codepoint
-    }
-
-    fn pop_key(&self) -> u8 {
-        let mut buf = [0];
-        loop {
-            if let Some(key) = self.try_read_key_with_buf(&mut buf) {
-                return key;
-            }
-        }
-    }
-
-    fn try_read_key_with_buf(&self, buf: &mut [u8]) -> Option<u8> {
-        if let Some(1) = sys_read(0, buf) {
-            return Some(buf[0]);
-        }
-        None
-    }
-
+    /// Read a line, the terminal handles echo and line editing
+    ///
+    /// returns `"\x04"` at the end of file
     pub fn read_line(&self) -> String {
         // DONE: allocate string
         // DONE: read from input buffer
-        //       - maybe char by char?
-        // DONE: handle backspace / enter...
         // DONE: return string
-        let mut string = String::new();
+        let mut line = vec![];
+        let mut buf = [0; 128];
         loop {
-            let ch = self.pop_key();
-
-            match ch {
-                0x0D => {
-                    stdout().write("\n");
-                    break;
-                },
-                0x03 => {
-                    string.clear();
-                    break;
-                },
-                0x04 => {
-                    if string.is_empty() {
-                        stdout().write("\n");
-                        string.push('\x04');
-                        break;
-                    }
-                },
-                0x08 | 0x7F if !string.is_empty() => {
-                    if !string.is_empty() {
-                        stdout().write("\x08 \x08");
-                        string.pop();
+            match sys_read(0, &mut buf) {
+                Some(0) | None => {
+                    if line.is_empty() {
+                        return "\x04".to_string();
                     }
+                    break;
                 }
-                _ => {
-                    if Self::is_utf8(ch) {
-                        let utf_char = char::from_u32(self.to_utf8(ch)).unwrap();
-                        string.push(utf_char);
-                        print!("{}", utf_char);
-                    } else {
-                        string.push(ch as char);
-                        print!("{}", ch as char);
+                Some(len) => {
+                    line.extend_from_slice(&buf[..len]);
+                    if line.last() == Some(&b'\n') {
+                        line.pop();
+                        break;
                     }
                 }
             }
         }
-        string
+        String::from_utf8_lossy(&line).to_string()
+    }
+
+    /// Current terminal settings
+    pub fn termios(&self) -> Option<Termios> {
+        let mut termios = Termios::default();
+        sys_ioctl(0, TCGETS, &mut termios as *mut Termios as usize).map(|_| termios)
+    }
+
+    /// Apply terminal settings, e.g. to switch to raw mode
+    pub fn set_termios(&self, termios: &Termios) -> bool {
+        sys_ioctl(0, TCSETS, termios as *const Termios as usize).is_some()
     }
 }
 
@@ -109,7 +62,7 @@ impl Stdout {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(1, s.as_bytes());
+        write_all(1, s.as_bytes());
     }
 }
 
@@ -119,7 +72,17 @@ impl Stderr {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(2, s.as_bytes());
+        write_all(2, s.as_bytes());
+    }
+}
+
+/// Write the whole buffer, a pipe may take only part of it at a time
+fn write_all(fd: u8, mut buf: &[u8]) {
+    while !buf.is_empty() {
+        match sys_write(fd, buf) {
+            Some(len) if len > 0 => buf = &buf[len..],
+            _ => break,
+        }
     }
 }
 
@@ -11,7 +11,9 @@ extern crate syscall_def;
 #[macro_use]
 pub mod io;
 pub mod allocator;
+pub mod signal;
 pub mod sync;
+pub mod time;
 pub extern crate alloc;
 
 mod syscall;
@@ -23,8 +25,15 @@ pub use alloc::string::{ String, ToString };
 pub use alloc::vec::Vec;
 pub use chrono::*;
 pub use io::*;
+pub use signal::{SignalHandler, signal};
 pub use sync::*;
 pub use syscall::*;
+pub use time::{date, set_date, uptime};
+pub use syscall_def::fs::{O_CLOEXEC, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY};
+pub use syscall_def::mman::{
+    MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE,
+};
+pub use syscall_def::wait::{ExitStatus, WAIT_ANY, WNOHANG};
 
 pub fn init() {
     #[cfg(feature = "brk_alloc")]
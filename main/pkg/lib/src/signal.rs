@@ -0,0 +1,58 @@
+use crate::*;
+use syscall_def::Syscall;
+
+pub use syscall_def::signal::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignalHandler {
+    Default,
+    Ignore,
+    Handler(extern "C" fn(usize)),
+}
+
+impl SignalHandler {
+    fn from_raw(raw: usize) -> Self {
+        match raw {
+            SIG_DFL => Self::Default,
+            SIG_IGN => Self::Ignore,
+            f => Self::Handler(unsafe { core::mem::transmute::<usize, extern "C" fn(usize)>(f) }),
+        }
+    }
+
+    fn raw(self) -> usize {
+        match self {
+            Self::Default => SIG_DFL,
+            Self::Ignore => SIG_IGN,
+            Self::Handler(f) => f as usize,
+        }
+    }
+}
+
+/// Handlers return here, with the signal frame on top of the stack
+#[unsafe(naked)]
+extern "C" fn sigreturn_trampoline() -> ! {
+    core::arch::naked_asm!(
+        "mov rax, {}",
+        "int 0x80",
+        "ud2",
+        const Syscall::Sigreturn as usize,
+    )
+}
+
+/// Set the action for a signal, returns the previous one
+pub fn signal(signal: usize, handler: SignalHandler) -> Option<SignalHandler> {
+    let old = sys_sigaction(signal, handler.raw(), sigreturn_trampoline as usize)?;
+    Some(SignalHandler::from_raw(old))
+}
+
+pub fn block_signals(set: u64) -> Option<u64> {
+    sys_sigprocmask(SIG_BLOCK, set)
+}
+
+pub fn unblock_signals(set: u64) -> Option<u64> {
+    sys_sigprocmask(SIG_UNBLOCK, set)
+}
+
+pub fn raise(signal: usize) -> bool {
+    sys_kill(sys_get_pid(), signal)
+}
@@ -1,6 +1,8 @@
 use crate::*;
 
-use core::sync::atomic::{ AtomicBool, Ordering };
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{ AtomicBool, AtomicU32, Ordering };
 
 pub struct SpinLock {
     bolt: AtomicBool,
@@ -48,6 +50,7 @@ impl Semaphore {
         Semaphore { key }
     }
 
+    /// Create the semaphore with `value`, or open it if another process already has
     #[inline(always)]
     pub fn init(&self, value: usize) -> bool {
         sys_new_sem(self.key, value)
@@ -60,10 +63,26 @@ impl Semaphore {
     }
 
     #[inline(always)]
-    pub fn wait(&self) {
+    pub fn wait(&self) -> bool {
         sys_sem_wait(self.key)
     }
 
+    #[inline(always)]
+    pub fn try_wait(&self) -> bool {
+        sys_sem_try_wait(self.key)
+    }
+
+    /// Wait at most `ms` milliseconds, returns false if the time is up
+    #[inline(always)]
+    pub fn wait_timeout(&self, ms: u64) -> bool {
+        sys_sem_timed_wait(self.key, ms)
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> Option<usize> {
+        sys_sem_value(self.key)
+    }
+
     #[inline(always)]
     pub fn free(&self) -> bool {
         sys_rm_sem(self.key)
@@ -72,6 +91,494 @@ impl Semaphore {
 
 unsafe impl Sync for Semaphore {}
 
+/// Kernel mutex, identified by its key like a `Semaphore`
+///
+/// unlike `Mutex`, it always enters the kernel, which lends the priority
+/// of the waiters to the owner until it unlocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KernelMutex {
+    key: u32,
+}
+
+impl KernelMutex {
+    pub const fn new(key: u32) -> Self {
+        KernelMutex { key }
+    }
+
+    /// Create the mutex unlocked, or open it if another process already has
+    #[inline(always)]
+    pub fn init(&self) -> bool {
+        sys_new_mutex(self.key)
+    }
+
+    #[inline(always)]
+    pub fn lock(&self) -> bool {
+        sys_mutex_lock(self.key)
+    }
+
+    #[inline(always)]
+    pub fn unlock(&self) -> bool {
+        sys_mutex_unlock(self.key)
+    }
+
+    #[inline(always)]
+    pub fn free(&self) -> bool {
+        sys_rm_mutex(self.key)
+    }
+}
+
+pub use syscall_def::mq::{MQ_MAX_MSGS, MQ_MAX_MSG_SIZE, MQ_PRIO_MAX};
+
+/// Kernel message queue, identified by its key like a `Semaphore`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MessageQueue {
+    key: u32,
+}
+
+impl MessageQueue {
+    pub const fn new(key: u32) -> Self {
+        MessageQueue { key }
+    }
+
+    /// Create the queue with room for `capacity` messages of up to `msg_size` bytes,
+    /// an existing queue is kept as it is
+    #[inline(always)]
+    pub fn init(&self, capacity: usize, msg_size: usize) -> bool {
+        sys_mq_open(self.key, capacity, msg_size)
+    }
+
+    /// Messages with a higher priority are received first, blocks while the queue is full
+    #[inline(always)]
+    pub fn send(&self, msg: &[u8], priority: usize) -> bool {
+        sys_mq_send(self.key, msg, priority)
+    }
+
+    /// Blocks while the queue is empty, the buffer must fit the message size
+    #[inline(always)]
+    pub fn recv(&self, buf: &mut [u8]) -> Option<usize> {
+        sys_mq_recv(self.key, buf)
+    }
+
+    #[inline(always)]
+    pub fn free(&self) -> bool {
+        sys_mq_unlink(self.key)
+    }
+}
+
+/// Memory shared with other processes through a key
+///
+/// the segment is freed once every process has detached it
+#[derive(Debug)]
+pub struct SharedMemory {
+    addr: usize,
+    size: usize,
+}
+
+impl SharedMemory {
+    /// Create the segment if needed and attach it
+    pub fn open(key: u32, size: usize) -> Option<Self> {
+        if !sys_shm_create(key, size) {
+            return None;
+        }
+        let addr = sys_shm_attach(key, None)?;
+        Some(Self { addr, size })
+    }
+
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.addr as *mut T
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        sys_shm_detach(self.addr);
+    }
+}
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// locked, and someone may be waiting in the kernel
+const CONTENDED: u32 = 2;
+
+/// Mutual exclusion between processes sharing memory, e.g. after a fork
+///
+/// built on futexes, only enters the kernel when the lock is contended
+pub struct Mutex<T: ?Sized> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(MutexGuard { mutex: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        // the lock is taken as contended, so whoever unlocks wakes the next waiter
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            sys_futex_wait(&self.state, CONTENDED);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            sys_futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Unlocks the `Mutex` when dropped
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Wait for a condition protected by a `Mutex`
+pub struct Condvar {
+    /// bumped by every notification
+    seq: AtomicU32,
+    /// processes waiting in the kernel
+    waiters: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlock the mutex until notified, it is locked again before returning
+    ///
+    /// the wait may end without a notification, check the condition in a loop
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+
+        // the sequence is read with the mutex held, a notification
+        // after unlocking changes it and the kernel does not block
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let seq = self.seq.load(Ordering::SeqCst);
+        drop(guard);
+
+        sys_futex_wait(&self.seq, seq);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+
+        mutex.lock()
+    }
+
+    /// Wait until `condition` returns false
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.notify(1);
+    }
+
+    pub fn notify_all(&self) {
+        self.notify(usize::MAX);
+    }
+
+    fn notify(&self, count: usize) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            sys_futex_wake(&self.seq, count);
+        }
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// held for writing
+const WRITER: u32 = u32::MAX;
+
+/// Many readers or one writer, readers are preferred
+///
+/// like `Mutex`, only enters the kernel when the lock is contended
+pub struct RwLock<T: ?Sized> {
+    /// number of readers, or `WRITER`
+    state: AtomicU32,
+    /// processes waiting in the kernel
+    waiters: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state < WRITER - 1 {
+                if self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return RwLockReadGuard { lock: self };
+                }
+                continue;
+            }
+            self.wait(state);
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            match self
+                .state
+                .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return RwLockWriteGuard { lock: self },
+                Err(state) => self.wait(state),
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Block while the state is still `state`
+    fn wait(&self, state: u32) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        sys_futex_wait(&self.state, state);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn wake(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            sys_futex_wake(&self.state, usize::MAX);
+        }
+    }
+
+    fn read_unlock(&self) {
+        // only writers can be waiting for the readers to leave
+        if self.state.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.wake();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.store(0, Ordering::SeqCst);
+        self.wake();
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+/// running, and someone is waiting in the kernel
+const WAITING: u32 = 2;
+const COMPLETE: u32 = 3;
+
+/// Run an initialization exactly once, others wait until it is done
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    pub fn call_once(&self, f: impl FnOnce()) {
+        let mut f = Some(f);
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    if let Some(f) = f.take() {
+                        f();
+                    }
+                    if self.state.swap(COMPLETE, Ordering::Release) == WAITING {
+                        sys_futex_wake(&self.state, usize::MAX);
+                    }
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(RUNNING) => {
+                    // let the running one know it has to wake us up
+                    if self
+                        .state
+                        .compare_exchange(RUNNING, WAITING, Ordering::Acquire, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        sys_futex_wait(&self.state, WAITING);
+                    }
+                }
+                Err(_) => {
+                    sys_futex_wait(&self.state, WAITING);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[macro_export]
 macro_rules! semaphore_array {
     [$($x:expr),+ $(,)?] => {
@@ -1,4 +1,9 @@
+use core::sync::atomic::AtomicU32;
 use syscall_def::Syscall;
+use syscall_def::futex::*;
+use syscall_def::mman::*;
+use syscall_def::time::*;
+use syscall_def::wait::*;
 
 #[inline(always)]
 pub fn sys_write(fd: u8, buf: &[u8]) -> Option<usize> {
@@ -30,18 +35,50 @@ pub fn sys_read(fd: u8, buf: &mut [u8]) -> Option<usize> {
     }
 }
 
+/// Collect an exited child, `pid` may be `WAIT_ANY`
+///
+/// returns `Some(0)` if `WNOHANG` is set and no matching child has exited,
+/// `None` if there is no such child
 #[inline(always)]
-pub fn sys_wait_pid(pid: u16) -> isize {
-    // DONE: try to get the return value for process
-    //        loop until the process is finished
-    loop {
-        let ret = syscall!(Syscall::WaitPid, pid as u64);
+pub fn sys_waitpid(pid: isize, options: usize, status: &mut ExitStatus) -> Option<u16> {
+    let mut raw = 0isize;
+    let ret = syscall!(
+        Syscall::WaitPid,
+        pid as u64,
+        options as u64,
+        &mut raw as *mut isize as u64
+    ) as isize;
+    if ret.is_negative() {
+        None
+    } else {
+        *status = ExitStatus::from_raw(raw);
+        Some(ret as u16)
+    }
+}
+
+#[inline(always)]
+pub fn sys_wait_pid(pid: u16) -> Option<ExitStatus> {
+    // DONE: block in the kernel until the process is finished
+    let mut status = ExitStatus::exited(0);
+    sys_waitpid(pid as isize, 0, &mut status)?;
+    Some(status)
+}
 
-        if ret != 0xBEE {
-            return ret as isize;
-        }
+/// Block until any child exits
+#[inline(always)]
+pub fn wait() -> Option<(u16, ExitStatus)> {
+    let mut status = ExitStatus::exited(0);
+    let pid = sys_waitpid(WAIT_ANY, 0, &mut status)?;
+    Some((pid, status))
+}
 
-        core::hint::spin_loop();
+/// Collect any exited child without blocking
+#[inline(always)]
+pub fn try_wait() -> Option<(u16, ExitStatus)> {
+    let mut status = ExitStatus::exited(0);
+    match sys_waitpid(WAIT_ANY, WNOHANG, &mut status)? {
+        0 => None,
+        pid => Some((pid, status)),
     }
 }
 
@@ -57,12 +94,21 @@ pub fn sys_stat() {
 
 #[inline(always)]
 pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
-    syscall!(Syscall::Allocate, layout as *const _) as *mut u8
+    syscall!(
+        Syscall::Allocate,
+        layout.size() as u64,
+        layout.align() as u64
+    ) as *mut u8
 }
 
 #[inline(always)]
 pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> usize {
-    syscall!(Syscall::Deallocate, ptr, layout as *const _)
+    syscall!(
+        Syscall::Deallocate,
+        ptr as u64,
+        layout.size() as u64,
+        layout.align() as u64
+    )
 }
 
 #[inline(always)]
@@ -73,7 +119,44 @@ pub fn sys_fork() -> u16 {
 
 #[inline(always)]
 pub fn sys_spawn(path: &str) -> u16 {
-    syscall!(Syscall::Spawn, path.as_ptr() as u64, path.len() as u64) as u16
+    sys_spawn_with(path, 0, 1)
+}
+
+/// Spawn with stdin and stdout taken from the given fds, the other fds are inherited
+#[inline(always)]
+pub fn sys_spawn_with(path: &str, stdin: u8, stdout: u8) -> u16 {
+    syscall!(
+        Syscall::Spawn,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        stdin as u64 | (stdout as u64) << 8
+    ) as u16
+}
+
+/// Create a pipe, returns the read and the write end
+#[inline(always)]
+pub fn sys_pipe() -> Option<(u8, u8)> {
+    sys_pipe_with(0)
+}
+
+/// Create a pipe with flags from `syscall_def::fs`, e.g. `O_CLOEXEC`
+#[inline(always)]
+pub fn sys_pipe_with(flags: usize) -> Option<(u8, u8)> {
+    match syscall!(Syscall::Pipe, flags as u64) {
+        usize::MAX => None,
+        ret => Some((ret as u8, (ret >> 8) as u8)),
+    }
+}
+
+#[inline(always)]
+pub fn sys_yield() {
+    syscall!(Syscall::Yield);
+}
+
+/// Sleep for at least `ms` milliseconds
+#[inline(always)]
+pub fn sys_sleep(ms: u64) {
+    syscall!(Syscall::Sleep, ms);
 }
 
 #[inline(always)]
@@ -102,14 +185,183 @@ pub fn sys_sem_signal(key: u32) {
     _ = syscall!(Syscall::Sem, 2, key as u64)
 }
 
+/// Fails if the semaphore does not exist, or if waiting would deadlock
+/// and the kernel is set to fail such waits
+#[inline(always)]
+pub fn sys_sem_wait(key: u32) -> bool {
+    syscall!(Syscall::Sem, 3, key as u64) == 0
+}
+
+/// Decrease the count without blocking, fails if it is 0
+#[inline(always)]
+pub fn sys_sem_try_wait(key: u32) -> bool {
+    syscall!(Syscall::Sem, 4, key as u64) == 0
+}
+
+/// Wait at most `ms` milliseconds, fails if the time is up
 #[inline(always)]
-pub fn sys_sem_wait(key: u32) {
-    _ = syscall!(Syscall::Sem, 3, key as u64)
+pub fn sys_sem_timed_wait(key: u32, ms: u64) -> bool {
+    syscall!(Syscall::Sem, 5, key as u64, ms) == 0
+}
+
+#[inline(always)]
+pub fn sys_new_mutex(key: u32) -> bool {
+    syscall!(Syscall::Mutex, 0, key as u64) == 0
+}
+
+#[inline(always)]
+pub fn sys_rm_mutex(key: u32) -> bool {
+    syscall!(Syscall::Mutex, 1, key as u64) == 0
+}
+
+/// Blocks while another process owns the mutex, fails if the caller owns it
+#[inline(always)]
+pub fn sys_mutex_lock(key: u32) -> bool {
+    syscall!(Syscall::Mutex, 2, key as u64) == 0
+}
+
+/// Fails if the caller does not own the mutex
+#[inline(always)]
+pub fn sys_mutex_unlock(key: u32) -> bool {
+    syscall!(Syscall::Mutex, 3, key as u64) == 0
+}
+
+/// Print the holders and waiters of every semaphore
+#[inline(always)]
+pub fn sys_sem_dump() {
+    _ = syscall!(Syscall::Sem, 7)
+}
+
+#[inline(always)]
+pub fn sys_sem_value(key: u32) -> Option<usize> {
+    match syscall!(Syscall::Sem, 6, key as u64) {
+        usize::MAX => None,
+        value => Some(value),
+    }
+}
+
+#[inline(always)]
+pub fn sys_mq_open(key: u32, capacity: usize, msg_size: usize) -> bool {
+    syscall!(Syscall::MqOpen, key as u64, capacity as u64, msg_size as u64) == 0
+}
+
+#[inline(always)]
+pub fn sys_mq_unlink(key: u32) -> bool {
+    syscall!(Syscall::MqUnlink, key as u64) == 0
+}
+
+/// Blocks while the queue is full
+#[inline(always)]
+pub fn sys_mq_send(key: u32, msg: &[u8], priority: usize) -> bool {
+    syscall!(
+        Syscall::MqSend,
+        key as u64 | (priority as u64) << 32,
+        msg.as_ptr() as u64,
+        msg.len() as u64
+    ) == 0
+}
+
+/// Blocks while the queue is empty, returns the message size
+#[inline(always)]
+pub fn sys_mq_recv(key: u32, buf: &mut [u8]) -> Option<usize> {
+    match syscall!(Syscall::MqRecv, key as u64, buf.as_ptr() as u64, buf.len() as u64) {
+        usize::MAX => None,
+        len => Some(len),
+    }
+}
+
+#[inline(always)]
+pub fn sys_shm_create(key: u32, size: usize) -> bool {
+    syscall!(Syscall::Shm, 0, key as u64, size as u64) == 0
+}
+
+/// Attach the segment at `addr`, or where the kernel picks with `None`
+#[inline(always)]
+pub fn sys_shm_attach(key: u32, addr: Option<usize>) -> Option<usize> {
+    match syscall!(Syscall::Shm, 1, key as u64, addr.unwrap_or(0) as u64) {
+        usize::MAX => None,
+        addr => Some(addr),
+    }
+}
+
+#[inline(always)]
+pub fn sys_shm_detach(addr: usize) -> bool {
+    syscall!(Syscall::Shm, 2, addr as u64) == 0
+}
+
+/// Block while `futex` holds `expected`, returns at once if it does not
+///
+/// a waiter may wake up without a `sys_futex_wake`, so check the value again
+#[inline(always)]
+pub fn sys_futex_wait(futex: &AtomicU32, expected: u32) -> bool {
+    syscall!(
+        Syscall::Futex,
+        futex.as_ptr() as u64,
+        FUTEX_WAIT as u64,
+        expected as u64
+    ) == 0
+}
+
+/// Wake up at most `count` processes waiting on `futex`, returns how many woke up
+#[inline(always)]
+pub fn sys_futex_wake(futex: &AtomicU32, count: usize) -> usize {
+    match syscall!(
+        Syscall::Futex,
+        futex.as_ptr() as u64,
+        FUTEX_WAKE as u64,
+        count as u64
+    ) {
+        usize::MAX => 0,
+        woken => woken,
+    }
+}
+
+#[inline(always)]
+pub fn sys_ioctl(fd: u8, request: usize, arg: usize) -> Option<usize> {
+    match syscall!(Syscall::Ioctl, fd as u64, request as u64, arg as u64) {
+        usize::MAX => None,
+        ret => Some(ret),
+    }
 }
 
 #[inline(always)]
 pub fn sys_open(path: &str) -> u8 {
-    syscall!(Syscall::Open, path.as_ptr() as u64, path.len() as u64) as u8
+    sys_open_with(path, 0)
+}
+
+/// Open with flags from `syscall_def::fs`, e.g. `O_NONBLOCK`
+#[inline(always)]
+pub fn sys_open_with(path: &str, flags: usize) -> u8 {
+    syscall!(
+        Syscall::Open,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        flags as u64
+    ) as u8
+}
+
+/// Create a FIFO that unrelated processes can open by its path
+#[inline(always)]
+pub fn sys_mkfifo(path: &str) -> bool {
+    syscall!(Syscall::Mkfifo, path.as_ptr() as u64, path.len() as u64) == 0
+}
+
+/// Duplicate the fd to the lowest free one
+#[inline(always)]
+pub fn sys_dup(fd: u8) -> Option<u8> {
+    match syscall!(Syscall::Dup, fd as u64) {
+        usize::MAX => None,
+        ret => Some(ret as u8),
+    }
+}
+
+/// Make `new_fd` refer to the same resource as `fd`
+#[inline(always)]
+pub fn sys_dup2(fd: u8, new_fd: u8) -> Option<u8> {
+    match syscall!(Syscall::Dup2, fd as u64, new_fd as u64) {
+        usize::MAX => None,
+        ret => Some(ret as u8),
+    }
 }
 
 #[inline(always)]
@@ -125,3 +377,79 @@ pub fn sys_brk(addr: Option<usize>) -> Option<usize> {
         ret => Some(ret),
     }
 }
+
+/// Map `len` bytes at `addr`, or where the kernel picks with `None`
+///
+/// only `MAP_ANONYMOUS` mappings are supported, their pages are zeroed
+#[inline(always)]
+pub fn sys_mmap(addr: Option<usize>, len: usize, prot: usize, flags: usize) -> Option<usize> {
+    match syscall!(
+        Syscall::Mmap,
+        addr.unwrap_or(0) as u64,
+        len as u64,
+        (prot | flags << MAP_FLAGS_SHIFT) as u64
+    ) {
+        usize::MAX => None,
+        addr => Some(addr),
+    }
+}
+
+#[inline(always)]
+pub fn sys_munmap(addr: usize, len: usize) -> bool {
+    syscall!(Syscall::Munmap, addr as u64, len as u64) == 0
+}
+
+#[inline(always)]
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> bool {
+    syscall!(Syscall::Mprotect, addr as u64, len as u64, prot as u64) == 0
+}
+
+#[inline(always)]
+pub fn sys_kill(pid: u16, signal: usize) -> bool {
+    syscall!(Syscall::Kill, pid as u64, signal) == 0
+}
+
+#[inline(always)]
+pub fn sys_sigaction(signal: usize, handler: usize, restorer: usize) -> Option<usize> {
+    match syscall!(Syscall::Sigaction, signal, handler, restorer) {
+        usize::MAX => None,
+        old => Some(old),
+    }
+}
+
+#[inline(always)]
+pub fn sys_sigprocmask(how: usize, set: u64) -> Option<u64> {
+    match syscall!(Syscall::Sigprocmask, how, set) {
+        usize::MAX => None,
+        old => Some(old as u64),
+    }
+}
+
+/// Set the scheduling priority of a process, 0 is the highest
+///
+/// `pid` 0 means the calling process, returns the old priority
+#[inline(always)]
+pub fn sys_set_priority(pid: u16, priority: usize) -> Option<usize> {
+    match syscall!(Syscall::SetPriority, pid as u64, priority) {
+        usize::MAX => None,
+        old => Some(old),
+    }
+}
+
+/// Set the scheduling priority of the calling process
+#[inline(always)]
+pub fn nice(priority: usize) -> Option<usize> {
+    sys_set_priority(0, priority)
+}
+
+#[inline(always)]
+pub fn sys_clock_gettime(clock: usize) -> Option<TimeSpec> {
+    let mut time = TimeSpec::default();
+    let ret = syscall!(Syscall::ClockGetTime, clock, &mut time as *mut TimeSpec);
+    if ret == 0 { Some(time) } else { None }
+}
+
+#[inline(always)]
+pub fn sys_set_time(time: &TimeSpec) -> bool {
+    syscall!(Syscall::SetTime, CLOCK_REALTIME, time as *const TimeSpec) == 0
+}
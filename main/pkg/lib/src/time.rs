@@ -0,0 +1,24 @@
+use crate::*;
+use core::time::Duration;
+
+pub use syscall_def::time::*;
+
+/// Current wall-clock time
+pub fn date() -> Option<DateTime<Utc>> {
+    let time = sys_clock_gettime(CLOCK_REALTIME)?;
+    DateTime::from_timestamp(time.sec, time.nsec as u32)
+}
+
+/// Set the wall-clock time
+pub fn set_date(date: DateTime<Utc>) -> bool {
+    sys_set_time(&TimeSpec {
+        sec: date.timestamp(),
+        nsec: date.timestamp_subsec_nanos() as i64,
+    })
+}
+
+/// Time since boot
+pub fn uptime() -> Option<Duration> {
+    let time = sys_clock_gettime(CLOCK_MONOTONIC)?;
+    Some(Duration::new(time.sec as u64, time.nsec as u32))
+}
@@ -0,0 +1,10 @@
+/// `Open` access modes, FIFOs are opened either for reading or for writing
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_RDWR: usize = 2;
+pub const O_ACCMODE: usize = 3;
+
+/// `Open` flag: reads return 0 bytes instead of blocking when no data is available
+pub const O_NONBLOCK: usize = 0x800;
+/// `Open` and `Pipe` flag: the fd is not inherited by spawned processes
+pub const O_CLOEXEC: usize = 0x80000;
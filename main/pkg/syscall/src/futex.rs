@@ -0,0 +1,4 @@
+/// Block while the word at the address still holds the expected value
+pub const FUTEX_WAIT: usize = 0;
+/// Wake up to the given number of waiters on the address
+pub const FUTEX_WAKE: usize = 1;
@@ -2,7 +2,15 @@
 
 use num_enum::FromPrimitive;
 
+pub mod fs;
+pub mod futex;
 pub mod macros;
+pub mod mman;
+pub mod mq;
+pub mod signal;
+pub mod time;
+pub mod tty;
+pub mod wait;
 
 #[repr(usize)]
 #[derive(Clone, Debug, FromPrimitive)]
@@ -12,16 +20,50 @@ pub enum Syscall {
     Open = 2,
     Close = 3,
 
+    Mmap = 9,
+    Mprotect = 10,
+    Munmap = 11,
     Brk = 12,
 
+    Sigaction = 13,
+    Sigprocmask = 14,
+    Sigreturn = 15,
+    Ioctl = 16,
+
+    Pipe = 22,
+
+    Dup = 32,
+    Dup2 = 33,
+
+    Yield = 24,
+
+    Shm = 29,
+    Sleep = 35,
+
     GetPid = 39,
     
     Fork = 58,
     Spawn = 59,
     Exit = 60,
     WaitPid = 61,
+    Kill = 62,
 
     Sem = 66,
+    Mutex = 67,
+
+    Mkfifo = 133,
+
+    SetPriority = 141,
+
+    Futex = 202,
+
+    SetTime = 227,
+    ClockGetTime = 228,
+
+    MqOpen = 240,
+    MqUnlink = 241,
+    MqSend = 242,
+    MqRecv = 243,
 
     ListDir = 65531,
     Stat = 65532,
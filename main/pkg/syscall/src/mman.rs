@@ -0,0 +1,20 @@
+/// Pages may not be accessed
+pub const PROT_NONE: usize = 0;
+/// Pages may be read
+pub const PROT_READ: usize = 1;
+/// Pages may be written, which implies they may be read
+pub const PROT_WRITE: usize = 2;
+/// Pages may be executed
+pub const PROT_EXEC: usize = 4;
+
+/// Changes are seen by every process sharing the mapping
+pub const MAP_SHARED: usize = 0x01;
+/// Changes are private to the address space
+pub const MAP_PRIVATE: usize = 0x02;
+/// Place the mapping exactly at the address, replacing what is there
+pub const MAP_FIXED: usize = 0x10;
+/// The mapping is not backed by a file and starts zeroed
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// `Mmap` takes the flags above the protection
+pub const MAP_FLAGS_SHIFT: usize = 8;
@@ -0,0 +1,6 @@
+/// Most messages a queue can hold
+pub const MQ_MAX_MSGS: usize = 64;
+/// Largest message size a queue can be opened with
+pub const MQ_MAX_MSG_SIZE: usize = 4096;
+/// Priorities are in `0..MQ_PRIO_MAX`, higher ones are received first
+pub const MQ_PRIO_MAX: usize = 32;
@@ -0,0 +1,52 @@
+/// Number of signals, signal 0 only checks whether the target exists
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+/// Handler value for the default action
+pub const SIG_DFL: usize = 0;
+/// Handler value for ignoring the signal
+pub const SIG_IGN: usize = 1;
+
+/// `Sigprocmask` operations
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Signals that can neither be caught, ignored nor blocked
+pub const UNCATCHABLE: u64 = 1 << SIGKILL;
+
+#[inline]
+pub const fn is_valid(signal: usize) -> bool {
+    signal > 0 && signal < NSIG
+}
+
+#[inline]
+pub const fn mask(signal: usize) -> u64 {
+    1 << signal
+}
+
+/// Whether the default action of the signal is to ignore it,
+/// all other signals terminate the process
+#[inline]
+pub const fn default_ignored(signal: usize) -> bool {
+    matches!(signal, SIGCHLD | SIGCONT | SIGURG | SIGWINCH)
+}
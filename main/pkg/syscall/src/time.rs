@@ -0,0 +1,44 @@
+/// Wall-clock time since the Unix epoch, can be set
+pub const CLOCK_REALTIME: usize = 0;
+/// Time since boot, never goes backwards
+pub const CLOCK_MONOTONIC: usize = 1;
+
+pub const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Time as passed by `ClockGetTime` and `SetTime`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpec {
+    pub sec: i64,
+    /// always in `0..NANOS_PER_SEC`
+    pub nsec: i64,
+}
+
+impl TimeSpec {
+    #[inline]
+    pub const fn from_nanos(nanos: i64) -> Self {
+        Self {
+            sec: nanos.div_euclid(NANOS_PER_SEC),
+            nsec: nanos.rem_euclid(NANOS_PER_SEC),
+        }
+    }
+
+    #[inline]
+    pub const fn as_nanos(&self) -> i64 {
+        self.sec * NANOS_PER_SEC + self.nsec
+    }
+
+    /// `None` if the time does not fit in `i64` nanoseconds
+    #[inline]
+    pub const fn checked_as_nanos(&self) -> Option<i64> {
+        match self.sec.checked_mul(NANOS_PER_SEC) {
+            Some(nanos) => nanos.checked_add(self.nsec),
+            None => None,
+        }
+    }
+
+    #[inline]
+    pub const fn is_valid(&self) -> bool {
+        self.nsec >= 0 && self.nsec < NANOS_PER_SEC
+    }
+}
@@ -0,0 +1,61 @@
+/// `Ioctl` request: copy the terminal settings to a `Termios`
+pub const TCGETS: usize = 0x5401;
+/// `Ioctl` request: apply the settings from a `Termios`
+pub const TCSETS: usize = 0x5402;
+
+/// `iflag`: translate a carriage return into a newline
+pub const ICRNL: u32 = 0x100;
+
+/// `lflag`: generate signals for the interrupt character
+pub const ISIG: u32 = 0x1;
+/// `lflag`: canonical mode, input is made available line by line
+pub const ICANON: u32 = 0x2;
+/// `lflag`: echo input characters
+pub const ECHO: u32 = 0x8;
+
+/// Indices into `Termios::cc`
+pub const VINTR: usize = 0;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const NCCS: usize = 8;
+
+/// Terminal settings, as passed by `TCGETS` and `TCSETS`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Termios {
+    pub iflag: u32,
+    pub lflag: u32,
+    /// control characters
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    fn default() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03; // Ctrl-C
+        cc[VERASE] = 0x7F; // DEL
+        cc[VKILL] = 0x15; // Ctrl-U
+        cc[VEOF] = 0x04; // Ctrl-D
+
+        Self {
+            iflag: ICRNL,
+            lflag: ISIG | ICANON | ECHO,
+            cc,
+        }
+    }
+}
+
+impl Termios {
+    #[inline]
+    pub const fn is_canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    /// Pass every byte through as it arrives, without echo or signals
+    #[inline]
+    pub fn make_raw(&mut self) {
+        self.iflag &= !ICRNL;
+        self.lflag &= !(ISIG | ICANON | ECHO);
+    }
+}
@@ -0,0 +1,79 @@
+/// Wait for any child process
+pub const WAIT_ANY: isize = -1;
+
+/// Return immediately if no child has exited yet
+pub const WNOHANG: usize = 1;
+
+/// Exit status of a child process, as reported by `WaitPid`
+///
+/// encoded like POSIX: a normal exit keeps the exit code above the low byte,
+/// a process killed by a signal keeps the signal number in the low 7 bits
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus(isize);
+
+impl ExitStatus {
+    #[inline]
+    pub const fn exited(code: isize) -> Self {
+        Self(code << 8)
+    }
+
+    #[inline]
+    pub const fn signaled(signal: usize) -> Self {
+        Self((signal & 0x7f) as isize)
+    }
+
+    #[inline]
+    pub const fn from_raw(raw: isize) -> Self {
+        Self(raw)
+    }
+
+    #[inline]
+    pub const fn raw(self) -> isize {
+        self.0
+    }
+
+    /// Exit code of a process that exited normally
+    #[inline]
+    pub const fn code(self) -> Option<isize> {
+        if self.0 & 0x7f == 0 {
+            Some(self.0 >> 8)
+        } else {
+            None
+        }
+    }
+
+    /// Signal that killed the process
+    #[inline]
+    pub const fn signal(self) -> Option<usize> {
+        if self.0 & 0x7f != 0 {
+            Some((self.0 & 0x7f) as usize)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub const fn success(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl core::fmt::Debug for ExitStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.code(), self.signal()) {
+            (Some(code), _) => write!(f, "exited({})", code),
+            (_, Some(signal)) => write!(f, "signaled({})", signal),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl core::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.code(), self.signal()) {
+            (Some(code), _) => write!(f, "exit code {}", code),
+            (_, Some(signal)) => write!(f, "killed by signal {}", signal),
+            _ => unreachable!(),
+        }
+    }
+}